@@ -1,5 +1,11 @@
-use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
-use uniaz::UniAz;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use uniaz::{TokenEncoding, UniAz};
+
+/// Sample texts for comparing token encodings.
+const CORPORA: [(&str, &str); 2] = [
+    ("Chinese", include_str!("corpus/zh.txt")),
+    ("Mixed", include_str!("corpus/mixed.txt")),
+];
 
 /// Benchmark for single character encryption
 fn bench_encrypt_char(c: &mut Criterion) {
@@ -138,10 +144,72 @@ fn bench_roundtrip(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark for the radix and compact token encodings, reporting output size
+fn bench_token_encoding(c: &mut Criterion) {
+    let encodings = [
+        ("Radix", UniAz::builder().envelope(true).build()),
+        (
+            "Compact",
+            UniAz::builder().token_encoding(TokenEncoding::Compact).build(),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("token_encoding");
+
+    for (corpus_name, corpus) in CORPORA {
+        let radix_len = encodings[0].1.encrypt_str(corpus).len();
+        for (encoding_name, uni_az) in &encodings {
+            let len = uni_az.encrypt_str(corpus).len();
+            println!(
+                "{corpus_name}/{encoding_name}: {} chars -> {len} bytes ({:.1}% of radix)",
+                corpus.chars().count(),
+                len as f64 * 100.0 / radix_len as f64
+            );
+            group.bench_with_input(
+                BenchmarkId::new(*encoding_name, corpus_name),
+                &corpus,
+                |b, &corpus| {
+                    b.iter(|| uni_az.encrypt_str(black_box(corpus)));
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+/// Round counts compared by the rounds benchmark.
+const ROUNDS: [usize; 6] = [1, 2, 3, 4, 6, 8];
+
+/// Benchmark for string throughput against the cipher round count
+fn bench_rounds(c: &mut Criterion) {
+    let (_, corpus) = CORPORA[0];
+
+    let mut group = c.benchmark_group("rounds");
+    group.throughput(Throughput::Elements(corpus.chars().count() as u64));
+
+    for rounds in ROUNDS {
+        let uni_az = UniAz::builder().rounds(rounds).build();
+        group.bench_with_input(BenchmarkId::new("encrypt_str", rounds), &corpus, |b, &corpus| {
+            b.iter(|| uni_az.encrypt_str(black_box(corpus)));
+        });
+        let encrypted = uni_az.encrypt_str(corpus);
+        group.bench_with_input(
+            BenchmarkId::new("decrypt_str", rounds),
+            &encrypted,
+            |b, encrypted| {
+                b.iter(|| uni_az.decrypt_str(black_box(encrypted)).unwrap());
+            },
+        );
+    }
+
+    group.finish();
+}
+
 /// Benchmark for UniAz initialization
 fn bench_initialization(c: &mut Criterion) {
     c.bench_function("UniAz::new", |b| {
-        b.iter(|| UniAz::new());
+        b.iter(UniAz::new);
     });
 }
 
@@ -152,6 +220,8 @@ criterion_group!(
     bench_decrypt_char,
     bench_encrypt_str,
     bench_decrypt_str,
-    bench_roundtrip
+    bench_roundtrip,
+    bench_token_encoding,
+    bench_rounds
 );
 criterion_main!(benches);
@@ -0,0 +1,20 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use uniaz::Cipher;
+
+// Input layout: iteration count, alphabet bytes, 0xFF, input bytes.
+// Bytes are read as Latin-1 characters, so the input may contain symbols
+// outside the alphabet.
+fuzz_target!(|data: &[u8]| {
+    let Some((&iteration, rest)) = data.split_first() else { return };
+    let split = rest.iter().position(|&b| b == 0xFF).unwrap_or(rest.len());
+    let alphabet: String = rest[..split].iter().copied().map(char::from).collect();
+    let input: String = rest.get(split + 1..).unwrap_or_default().iter().copied().map(char::from).collect();
+
+    let Ok(cipher) = Cipher::try_new(&alphabet) else { return };
+    let iteration = usize::from(iteration % 4);
+    let encrypted = cipher.encrypt(&input, iteration);
+    assert_eq!(encrypted.chars().count(), input.chars().count());
+    assert_eq!(cipher.decrypt(&encrypted, iteration), input);
+});
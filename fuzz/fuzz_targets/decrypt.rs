@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use uniaz::UniAz;
+
+fuzz_target!(|token: &str| {
+    let u = UniAz::new();
+    if let Ok(c) = u.decrypt(token) {
+        assert_eq!(u.decrypt(&u.encrypt(c)), Ok(c));
+    }
+});
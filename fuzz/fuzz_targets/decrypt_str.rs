@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use uniaz::UniAz;
+
+fuzz_target!(|text: &str| {
+    let _ = UniAz::new().decrypt_str(text);
+    let _ = UniAz::builder()
+        .key(b"fuzz")
+        .authenticated(true)
+        .build()
+        .decrypt_str(text);
+});
@@ -0,0 +1,298 @@
+//! Statistical checks of the diffusion of [`Cipher`].
+//!
+//! [`analyze`] encrypts pseudo-random inputs of a fixed width and measures
+//! how far the outputs are from those of a random permutation: how many
+//! output digits change when one input digit changes, how evenly digits and
+//! bits are distributed at each output position, and how often outputs or
+//! their prefixes collide. [`Thresholds`] turns a [`Report`] into a pass or
+//! fail verdict, so regressions in diffusion fail tests.
+//!
+//! The inputs are drawn from a fixed pseudo-random sequence, so the same
+//! cipher and parameters always give the same report.
+//!
+//! # Examples
+//!
+//! ```
+//! use uniaz::analysis::{analyze, Thresholds};
+//! use uniaz::Cipher;
+//!
+//! let cipher = Cipher::new("abcdefghijklmnopqrstuvwxyz");
+//! let report = analyze(&cipher, 5, 2, 5_000);
+//! assert_eq!(report.collisions, 0);
+//! assert!(Thresholds::default().check(&report).is_ok(), "{report:?}");
+//! ```
+
+use crate::Cipher;
+use std::collections::{HashMap, HashSet};
+
+/// Measurements of one cipher at one input width and iteration count.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// Mean fraction of output digits that change when one input digit
+    /// changes, divided by the `1 - 1/radix` of a random permutation; ideally 1.
+    pub avalanche: f64,
+    /// The lowest such ratio over the changed input position, which exposes
+    /// digits that barely influence the output.
+    pub worst_avalanche: f64,
+    /// Largest z-score, over output positions, of the chi-square statistic
+    /// of the digit counts against uniform digits; about ±2 for random output.
+    pub digit_bias: f64,
+    /// Largest absolute z-score, over output positions and bits of the digit
+    /// values, of the count of set bits against uniform digits.
+    pub bit_bias: f64,
+    /// Number of distinct inputs that encrypt to an output already seen; 0
+    /// for a permutation.
+    pub collisions: usize,
+    /// Collisions among output prefixes, divided by the number expected for
+    /// random output, or `None` for single-digit inputs. The prefix length
+    /// is chosen so that about as many prefixes exist as samples.
+    pub prefix_collisions: Option<f64>,
+}
+
+/// Limits a [`Report`] must stay within.
+///
+/// The defaults accept random output with a wide margin, at the sample
+/// counts used in this crate's tests (a few thousand and up).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thresholds {
+    /// Lowest and highest accepted [`Report::avalanche`].
+    pub avalanche: (f64, f64),
+    /// Lowest accepted [`Report::worst_avalanche`].
+    pub worst_avalanche: f64,
+    /// Highest accepted [`Report::digit_bias`].
+    pub digit_bias: f64,
+    /// Highest accepted [`Report::bit_bias`].
+    pub bit_bias: f64,
+    /// Lowest and highest accepted [`Report::prefix_collisions`].
+    pub prefix_collisions: (f64, f64),
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            avalanche: (0.95, 1.05),
+            worst_avalanche: 0.9,
+            digit_bias: 6.0,
+            bit_bias: 6.0,
+            prefix_collisions: (0.8, 1.2),
+        }
+    }
+}
+
+impl Thresholds {
+    /// Checks a report, listing every measurement outside its limits.
+    pub fn check(&self, report: &Report) -> Result<(), Vec<String>> {
+        let mut failures = Vec::new();
+        let (low, high) = self.avalanche;
+        if !(low..=high).contains(&report.avalanche) {
+            failures.push(format!("avalanche {:.3} outside {low}..={high}", report.avalanche));
+        }
+        if report.worst_avalanche < self.worst_avalanche {
+            failures.push(format!(
+                "worst avalanche {:.3} below {}",
+                report.worst_avalanche, self.worst_avalanche
+            ));
+        }
+        if report.digit_bias > self.digit_bias {
+            failures.push(format!("digit bias {:.2} above {}", report.digit_bias, self.digit_bias));
+        }
+        if report.bit_bias > self.bit_bias {
+            failures.push(format!("bit bias {:.2} above {}", report.bit_bias, self.bit_bias));
+        }
+        if report.collisions > 0 {
+            failures.push(format!("{} output collisions", report.collisions));
+        }
+        if let Some(ratio) = report.prefix_collisions {
+            let (low, high) = self.prefix_collisions;
+            if !(low..=high).contains(&ratio) {
+                failures.push(format!("prefix collisions {ratio:.3} outside {low}..={high}"));
+            }
+        }
+        if failures.is_empty() { Ok(()) } else { Err(failures) }
+    }
+}
+
+/// Measures [`Cipher::encrypt`] with `iteration` rounds on `samples`
+/// pseudo-random inputs of `width` alphabet characters.
+///
+/// # Panics
+///
+/// Panics if `width` or `samples` is 0.
+pub fn analyze(cipher: &Cipher, width: usize, iteration: usize, samples: usize) -> Report {
+    assert!(width > 0 && samples > 0, "width and samples must be positive");
+    let alphabet = cipher.alphabet();
+    let radix = alphabet.len();
+    let mut rng = SplitMix64(0x2545_F491_4F6C_DD1D);
+    let value = |c: char| {
+        alphabet.iter().position(|&a| a == c).expect("output stays in the alphabet")
+    };
+
+    let mut changed = vec![(0usize, 0usize); width];
+    let mut counts = vec![vec![0usize; radix]; width];
+    let mut outputs: HashSet<String> = HashSet::new();
+    let mut inputs: HashSet<Vec<usize>> = HashSet::new();
+    let mut collisions = 0;
+
+    for _ in 0..samples {
+        let digits: Vec<usize> = (0..width).map(|_| rng.below(radix)).collect();
+        let input: String = digits.iter().map(|&d| alphabet[d]).collect();
+        let output = cipher.encrypt(&input, iteration);
+        for (position, c) in output.chars().enumerate() {
+            counts[position][value(c)] += 1;
+        }
+        if inputs.insert(digits.clone()) && !outputs.insert(output.clone()) {
+            collisions += 1;
+        }
+
+        if radix > 1 {
+            let position = rng.below(width);
+            let mut flipped = digits;
+            flipped[position] = (flipped[position] + 1 + rng.below(radix - 1)) % radix;
+            let flipped: String = flipped.iter().map(|&d| alphabet[d]).collect();
+            let diff = cipher
+                .encrypt(&flipped, iteration)
+                .chars()
+                .zip(output.chars())
+                .filter(|(a, b)| a != b)
+                .count();
+            changed[position].0 += diff;
+            changed[position].1 += width;
+        }
+    }
+
+    let ideal = 1.0 - 1.0 / radix as f64;
+    let ratio = |(diff, total): (usize, usize)| diff as f64 / total as f64 / ideal;
+    let (diff, total) = changed.iter().fold((0, 0), |acc, c| (acc.0 + c.0, acc.1 + c.1));
+    let avalanche = if total == 0 { 1.0 } else { ratio((diff, total)) };
+    let worst_avalanche = changed
+        .iter()
+        .filter(|c| c.1 > 0)
+        .map(|&c| ratio(c))
+        .fold(avalanche, f64::min);
+
+    Report {
+        avalanche,
+        worst_avalanche,
+        digit_bias: counts.iter().map(|c| chi_square_z(c, samples)).fold(f64::MIN, f64::max),
+        bit_bias: counts.iter().map(|c| bit_z(c, samples)).fold(0.0, f64::max),
+        collisions,
+        prefix_collisions: prefix_collisions(&outputs, radix, width),
+    }
+}
+
+/// z-score of the chi-square statistic of digit counts against uniform digits.
+fn chi_square_z(counts: &[usize], samples: usize) -> f64 {
+    if counts.len() < 2 {
+        return 0.0;
+    }
+    let expected = samples as f64 / counts.len() as f64;
+    let chi_square: f64 = counts.iter().map(|&o| (o as f64 - expected).powi(2) / expected).sum();
+    let df = (counts.len() - 1) as f64;
+    (chi_square - df) / (2.0 * df).sqrt()
+}
+
+/// Largest absolute z-score of a bit of the digit values against uniform digits.
+fn bit_z(counts: &[usize], samples: usize) -> f64 {
+    let bits = usize::BITS - (counts.len() - 1).leading_zeros();
+    let n = samples as f64;
+    (0..bits)
+        .map(|bit| {
+            let with_bit = |d: &usize| d >> bit & 1 == 1;
+            let p = (0..counts.len()).filter(with_bit).count() as f64 / counts.len() as f64;
+            let observed: usize = (0..counts.len()).filter(with_bit).map(|d| counts[d]).sum();
+            (observed as f64 - n * p).abs() / (n * p * (1.0 - p)).sqrt()
+        })
+        .fold(0.0, f64::max)
+}
+
+/// Observed over expected collisions among output prefixes.
+fn prefix_collisions(outputs: &HashSet<String>, radix: usize, width: usize) -> Option<f64> {
+    if width < 2 || radix < 2 {
+        return None;
+    }
+    let n = outputs.len() as f64;
+    // About as many prefixes as samples, but shorter than the whole output.
+    let length = (n.ln() / (radix as f64).ln()).round().clamp(1.0, (width - 1) as f64) as usize;
+    let buckets = (radix as f64).powi(length as i32);
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    for output in outputs {
+        let end = output.char_indices().nth(length).map_or(output.len(), |(i, _)| i);
+        *seen.entry(&output[..end]).or_default() += 1;
+    }
+    let observed = n - seen.len() as f64;
+    let expected = n - buckets * (1.0 - (1.0 - 1.0 / buckets).powf(n));
+    (expected >= 1.0).then(|| observed / expected)
+}
+
+/// Small deterministic generator for reproducible inputs.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    /// A value below `bound`, with negligible bias for small bounds.
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Alphabets of the radices in use, with input widths giving at least
+    /// 10^5 possible inputs.
+    fn alphabets() -> Vec<(String, usize)> {
+        vec![
+            ("0123456789".into(), 6),
+            ("0123456789abcdef".into(), 5),
+            ("abcdefghijklmnopqrstuvwxyz".into(), 5),
+            ((0..=255u8).map(char::from).collect(), 3),
+        ]
+    }
+
+    #[test]
+    fn test_diffusion() {
+        for (alphabet, width) in alphabets() {
+            let cipher = Cipher::new(&alphabet);
+            let radix = alphabet.chars().count();
+            // A single round leaves decimal digits short of full diffusion.
+            let first = if radix < 16 { 2 } else { 1 };
+            for iteration in first..=3 {
+                let report = analyze(&cipher, width, iteration, 20_000);
+                let verdict = Thresholds::default().check(&report);
+                assert!(verdict.is_ok(), "radix {radix}, {iteration} rounds: {verdict:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_keyed_diffusion() {
+        let alphabet = crate::key::keyed_alphabet("abcdefghijklmnopqrstuvwxyz", b"secret");
+        let report = analyze(&Cipher::new(&alphabet), 5, 2, 20_000);
+        assert_eq!(Thresholds::default().check(&report), Ok(()));
+    }
+
+    #[test]
+    fn test_detects_weak_diffusion() {
+        // With two digits each position can only be kept or flipped, and
+        // changing one input digit changes just that output digit.
+        let report = analyze(&Cipher::new("01"), 16, 2, 20_000);
+        assert!(report.worst_avalanche < 0.2, "{report:?}");
+        assert_eq!(report.collisions, 0);
+        assert!(Thresholds::default().check(&report).is_err());
+
+        let report = analyze(&Cipher::new("0123456789"), 6, 1, 20_000);
+        let failures = Thresholds::default().check(&report).unwrap_err();
+        assert!(failures[0].starts_with("avalanche"), "{failures:?}");
+    }
+
+    #[test]
+    fn test_report_is_deterministic() {
+        let cipher = Cipher::new("abcdefghijklmnopqrstuvwxyz");
+        assert_eq!(analyze(&cipher, 4, 2, 1_000), analyze(&cipher, 4, 2, 1_000));
+        assert_eq!(analyze(&cipher, 1, 2, 1_000).prefix_collisions, None);
+    }
+}
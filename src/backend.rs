@@ -0,0 +1,47 @@
+use crate::Cipher;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+
+/// A length-preserving cipher over the letters of a token.
+///
+/// [`UniAz`](crate::UniAz) enciphers every token through this trait, so the
+/// crate's own [`Cipher`] and, with the `ff1` feature, the standardized
+/// `Ff1` are interchangeable; the `ff1` option of
+/// [`UniAzBuilder`](crate::UniAzBuilder) picks between them. Tokens only contain characters of the cipher's
+/// alphabet. The unwind-safety bounds keep `UniAz` usable with
+/// [`std::panic::catch_unwind`].
+pub(crate) trait TokenCipher: Send + Sync + UnwindSafe + RefUnwindSafe {
+    /// Enciphers a token with a public tweak.
+    ///
+    /// `rounds` is the configured round count; ciphers with a fixed number
+    /// of rounds, such as FF1, ignore it.
+    fn encipher(&self, input: &str, rounds: usize, tweak: u64) -> String;
+
+    /// Deciphers a token enciphered with the same rounds and tweak.
+    fn decipher(&self, input: &str, rounds: usize, tweak: u64) -> String;
+
+    /// Shortest token the cipher accepts, apart from the empty one.
+    ///
+    /// Shorter tokens are padded with the alphabet's zero digit before they
+    /// are enciphered.
+    fn min_len(&self) -> usize {
+        1
+    }
+
+    /// Longest token the cipher accepts.
+    fn max_len(&self) -> usize {
+        usize::MAX
+    }
+}
+
+impl TokenCipher for Cipher {
+    fn encipher(&self, input: &str, rounds: usize, tweak: u64) -> String {
+        self.encrypt_tweaked(input, rounds, tweak)
+    }
+
+    fn decipher(&self, input: &str, rounds: usize, tweak: u64) -> String {
+        self.decrypt_tweaked(input, rounds, tweak)
+    }
+}
+
+/// A boxed [`TokenCipher`] that keeps the auto traits of its owner.
+pub(crate) type BoxedTokenCipher = Box<dyn TokenCipher + UnwindSafe + RefUnwindSafe>;
@@ -0,0 +1,448 @@
+use crate::cipher::Cipher;
+#[cfg(feature = "ff1")]
+use crate::backend::BoxedTokenCipher;
+use crate::key::{self, MacKey, SecretKey};
+use crate::compress::MAX_EXPANDED_LENGTH;
+use crate::envelope::MAX_ROUNDS;
+#[cfg(feature = "ff1")]
+use crate::id::BLOCKLIST_MAX_ATTEMPTS;
+#[cfg(feature = "ff1")]
+use crate::{Ff1, U64_WIDTH};
+use crate::{FrequencyTable, TokenEncoding, TweakMode, UniAz, ALPHABET, DEFAULT_ROUNDS};
+#[cfg(feature = "normalization")]
+use crate::Normalization;
+use anybase::Converter;
+use zeroize::Zeroizing;
+
+/// Builder for [`UniAz`] instances with a non-default configuration.
+///
+/// # Examples
+///
+/// ```
+/// use uniaz::UniAzBuilder;
+///
+/// let uni_az = UniAzBuilder::new()
+///     .min_id_length(6)
+///     .blocklist(["bad", "word"])
+///     .build();
+/// let id = uni_az.encode_u64(42);
+/// assert_eq!(uni_az.decode_u64(&id).unwrap(), 42);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct UniAzBuilder {
+    min_id_length: usize,
+    blocklist: Vec<String>,
+    randomized: bool,
+    tweak_mode: TweakMode,
+    key: Option<SecretKey>,
+    key_id: u16,
+    authenticated: bool,
+    envelope: bool,
+    max_token_length: Option<usize>,
+    max_expanded_length: Option<usize>,
+    lenient: bool,
+    #[cfg(feature = "normalization")]
+    normalization: Option<Normalization>,
+    #[cfg(feature = "grapheme")]
+    grapheme_clusters: bool,
+    preserve_lines: bool,
+    token_encoding: TokenEncoding,
+    frequency_table: Option<FrequencyTable>,
+    compressed: bool,
+    rounds: Option<usize>,
+    #[cfg(feature = "ff1")]
+    ff1: bool,
+}
+
+impl UniAzBuilder {
+    /// Creates a builder with the default configuration, equivalent to [`UniAz::new`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the minimum length of tokens produced by [`UniAz::encode_u64`].
+    ///
+    /// Shorter tokens are left-padded before encryption, so padding changes the
+    /// whole token rather than appending filler letters. Defaults to `0`.
+    pub fn min_id_length(mut self, length: usize) -> Self {
+        self.min_id_length = length;
+        self
+    }
+
+    /// Sets words that [`UniAz::encode_u64`] must never produce as a substring.
+    ///
+    /// Words are matched case-insensitively; words containing characters other
+    /// than ASCII letters can never appear in a token and are ignored.
+    pub fn blocklist<I, S>(mut self, words: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.blocklist = words
+            .into_iter()
+            .map(|w| w.as_ref().to_ascii_lowercase())
+            .filter(|w| !w.is_empty() && w.chars().all(|c| c.is_ascii_lowercase()))
+            .collect();
+        self
+    }
+
+    /// Makes [`UniAz::encrypt_str`] non-deterministic.
+    ///
+    /// Each message gets a random nonce, emitted as a header token, that tweaks
+    /// the cipher for the whole message. Repeated messages and characters then
+    /// no longer produce the same tokens across messages. Defaults to `false`.
+    pub fn randomized(mut self, randomized: bool) -> Self {
+        self.randomized = randomized;
+        self
+    }
+
+    /// Sets how [`UniAz::encrypt_str`] derives the cipher tweak of each token.
+    ///
+    /// [`TweakMode::PositionV1`] mixes each character's index into its token,
+    /// so repeated characters no longer produce repeated tokens. The mode is
+    /// recorded in the envelope header. Defaults to [`TweakMode::None`].
+    pub fn tweak_mode(mut self, mode: TweakMode) -> Self {
+        self.tweak_mode = mode;
+        self
+    }
+
+    /// Sets a secret key for the instance.
+    ///
+    /// The key permutes the cipher alphabet, so tokens can only be decrypted by
+    /// an instance with the same key, and keys the tag of the
+    /// [authenticated](Self::authenticated) mode. Any byte string is accepted.
+    pub fn key(mut self, key: &[u8]) -> Self {
+        self.key = Some(SecretKey::new(key));
+        self
+    }
+
+    /// Sets the key id recorded in envelope headers.
+    ///
+    /// [`UniAz::decrypt_str`] rejects enveloped text carrying another key id
+    /// with [`DecryptError::UnknownKey`](crate::DecryptError::UnknownKey).
+    /// Defaults to `0`.
+    pub fn key_id(mut self, key_id: u16) -> Self {
+        self.key_id = key_id;
+        self
+    }
+
+    /// Makes [`UniAz::encrypt_str`] always write an envelope header.
+    ///
+    /// The header records the format version, alphabet, modes and key id, so
+    /// the text stays decodable if defaults change later. It is written
+    /// anyway whenever a mode or key is configured; without one, output is
+    /// the headerless v1 format unless this is set. Defaults to `false`.
+    pub fn envelope(mut self, envelope: bool) -> Self {
+        self.envelope = envelope;
+        self
+    }
+
+    /// Makes [`UniAz::encrypt_str`] append a tag authenticating the whole message.
+    ///
+    /// The tag is a keyed SipHash-2-4 MAC over all tokens, rendered as a
+    /// 14-letter token. [`UniAz::decrypt_str`] verifies it before decrypting
+    /// anything and fails with
+    /// [`DecryptError::AuthenticationFailed`](crate::DecryptError::AuthenticationFailed)
+    /// on mismatch. Requires a [key](Self::key). Defaults to `false`.
+    pub fn authenticated(mut self, authenticated: bool) -> Self {
+        self.authenticated = authenticated;
+        self
+    }
+
+    /// Sets the longest character token [`UniAz::decrypt`] and
+    /// [`UniAz::decrypt_str`] attempt to decode.
+    ///
+    /// Longer tokens fail with
+    /// [`DecryptError::TokenTooLong`](crate::DecryptError::TokenTooLong)
+    /// before any decryption work, which bounds the cost of hostile input.
+    /// Defaults to the number of letters needed to write U+10FFFF in the
+    /// alphabet's base, i.e. the longest token [`UniAz::encrypt`] produces.
+    pub fn max_token_length(mut self, length: usize) -> Self {
+        self.max_token_length = Some(length);
+        self
+    }
+
+    /// Sets the most characters [`UniAz::decrypt_str`] expands a
+    /// [compressed](Self::compressed) line to.
+    ///
+    /// A few letters of stream can expand to thousands of characters, so
+    /// longer lines fail with
+    /// [`DecryptError::TokenTooLong`](crate::DecryptError::TokenTooLong) as
+    /// soon as they pass the limit, which bounds the memory hostile input can
+    /// claim. Defaults to 1,048,576 characters.
+    pub fn max_expanded_length(mut self, length: usize) -> Self {
+        self.max_expanded_length = Some(length);
+        self
+    }
+
+    /// Makes [`UniAz::decrypt`] and [`UniAz::decrypt_str`] accept non-canonical tokens.
+    ///
+    /// By default only the exact token [`UniAz::encrypt`] produces for a
+    /// character is accepted, so each character has a single valid token and
+    /// tokens can be compared or deduplicated as strings. Other tokens that
+    /// decode to the same character, such as ones with padded digits or
+    /// misheard [pronounceable](TokenEncoding::Pronounceable) syllables, fail
+    /// with [`DecryptError::NonCanonical`](crate::DecryptError::NonCanonical)
+    /// unless this is set. Defaults to `false`.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Normalizes text to the given form before [`UniAz::encrypt_str`] encrypts it.
+    ///
+    /// Canonically equivalent inputs then produce identical tokens, and
+    /// [`UniAz::decrypt_str`] returns the normalized text. Decryption itself
+    /// never normalizes. Defaults to no normalization.
+    ///
+    /// Requires the `normalization` feature.
+    #[cfg(feature = "normalization")]
+    pub fn normalization(mut self, form: Normalization) -> Self {
+        self.normalization = Some(form);
+        self
+    }
+
+    /// Makes [`UniAz::encrypt_str`] group tokens by extended grapheme cluster.
+    ///
+    /// The tokens of all characters in one user-perceived character, such as
+    /// a flag or a family emoji, are joined by `-` into a single
+    /// space-separated group, so splitting the output at spaces never splits
+    /// a cluster. The mode is recorded in the envelope header.
+    /// Defaults to `false`.
+    ///
+    /// Requires the `grapheme` feature; decrypting grouped text does not.
+    #[cfg(feature = "grapheme")]
+    pub fn grapheme_clusters(mut self, grouped: bool) -> Self {
+        self.grapheme_clusters = grouped;
+        self
+    }
+
+    /// Makes [`UniAz::encrypt_str`] keep the line structure of the text.
+    ///
+    /// Newlines are not encrypted but written as literal newlines, so each
+    /// line of the text becomes one line of output and encrypted files can be
+    /// diffed line by line. The envelope header, and the nonce if any, take
+    /// the first line and the authentication tag the last. Such messages are
+    /// decoded strictly: tokens must be separated by exactly one space, and
+    /// the newlines are restored exactly. Defaults to `false`.
+    pub fn preserve_lines(mut self, preserve: bool) -> Self {
+        self.preserve_lines = preserve;
+        self
+    }
+
+    /// Sets how characters are written as letters before they are enciphered.
+    ///
+    /// [`TokenEncoding::Compact`] gives frequent characters 1-2 letter tokens,
+    /// [`TokenEncoding::Pronounceable`] gives syllables that are easy to read
+    /// aloud, and [`TokenEncoding::OrderPreserving`] gives unenciphered tokens
+    /// that sort like the source text.
+    /// It applies to [`UniAz::encrypt`] and [`UniAz::decrypt`] as well, and
+    /// is recorded in the envelope of [`UniAz::encrypt_str`] output.
+    /// Defaults to [`TokenEncoding::Radix`].
+    pub fn token_encoding(mut self, encoding: TokenEncoding) -> Self {
+        self.token_encoding = encoding;
+        self
+    }
+
+    /// Sets the table of [`TokenEncoding::Compact`] and of
+    /// [compressed](Self::compressed) text.
+    ///
+    /// The table is not recorded in the output, so text must be decrypted by
+    /// an instance with the same table. Defaults to
+    /// [`FrequencyTable::bundled`].
+    pub fn frequency_table(mut self, table: FrequencyTable) -> Self {
+        self.frequency_table = Some(table);
+        self
+    }
+
+    /// Makes [`UniAz::encrypt_str`] compress the text into a single stream token.
+    ///
+    /// Repeated characters and repeated substrings down to bigrams become
+    /// back-references, and other characters are written by their rank in
+    /// the [frequency table](Self::frequency_table), so the output is usually
+    /// much shorter than one token per character. The stream contains only
+    /// letters and is enciphered in short blocks. The mode is recorded in the
+    /// envelope header; with [`preserve_lines`](Self::preserve_lines), each
+    /// line is compressed on its own. Defaults to `false`.
+    pub fn compressed(mut self, compressed: bool) -> Self {
+        self.compressed = compressed;
+        self
+    }
+
+    /// Sets how many times the cipher is applied to each token.
+    ///
+    /// More rounds diffuse each letter further across its token at a
+    /// proportional cost in speed; the default of 2 already passes the
+    /// statistical checks of the `analysis` feature for the Latin alphabet.
+    /// Any other count is recorded in the envelope header of
+    /// [`UniAz::encrypt_str`] output, so [`UniAz::decrypt_str`] reads text
+    /// written with any count. Tokens of [`UniAz::encrypt`],
+    /// [`UniAz::encrypt_bytes`] and [`UniAz::encode_u64`] carry no header
+    /// and must be decrypted with the same count. Defaults to `2`.
+    pub fn rounds(mut self, rounds: usize) -> Self {
+        self.rounds = Some(rounds);
+        self
+    }
+
+    /// Enciphers tokens with FF1 (NIST SP 800-38G) instead of [`Cipher`].
+    ///
+    /// The [key](Self::key) is then used as is as the AES key and must be
+    /// 16, 24 or 32 bytes long, so tokens match any standard FF1
+    /// implementation; the key of the [authenticated](Self::authenticated)
+    /// mode is derived from it with the KDF of NIST SP 800-108 using
+    /// AES-CMAC. FF1 takes at least 5 letters over a-z, so
+    /// [`UniAz::encrypt`] always produces 5-letter tokens and
+    /// [`UniAz::encrypt_bytes`] slightly longer output; it has a fixed 10
+    /// rounds, so [`rounds`](Self::rounds) does not apply to it. The cipher
+    /// is recorded in the envelope header, and FF1 instances reject enveloped
+    /// `Cipher` text, as other instances reject FF1 text, with
+    /// [`DecryptError::UnsupportedFormat`](crate::DecryptError::UnsupportedFormat).
+    /// [`UniAz::decrypt_ct`] does not support FF1. Defaults to `false`.
+    ///
+    /// Requires the `ff1` feature.
+    #[cfg(feature = "ff1")]
+    pub fn ff1(mut self, ff1: bool) -> Self {
+        self.ff1 = ff1;
+        self
+    }
+
+    /// Builds the configured [`UniAz`] instance.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the authenticated mode is enabled without a key, if the
+    /// round count is not between 1 and 25, if
+    /// grapheme grouping or pronounceable tokens are combined with
+    /// compression, or if [`TokenEncoding::OrderPreserving`] is combined with
+    /// any option that breaks its order. With FF1, panics if the key is
+    /// missing or not a valid AES key, or if compact tokens, compression or a
+    /// [`min_id_length`](Self::min_id_length) above 34 are configured.
+    pub fn build(self) -> UniAz {
+        if self.authenticated && self.key.is_none() {
+            panic!("Authenticated mode requires a key");
+        }
+        let rounds = self.rounds.unwrap_or(DEFAULT_ROUNDS);
+        if !(1..=usize::from(MAX_ROUNDS)).contains(&rounds) {
+            panic!("Round count must be between 1 and {MAX_ROUNDS}, got {rounds}");
+        }
+        #[cfg(feature = "grapheme")]
+        if self.grapheme_clusters && self.compressed {
+            panic!("Grapheme grouping cannot be combined with compression");
+        }
+        if self.compressed && self.token_encoding == TokenEncoding::Pronounceable {
+            panic!("Pronounceable tokens cannot be combined with compression");
+        }
+        if self.token_encoding == TokenEncoding::OrderPreserving {
+            #[cfg(feature = "grapheme")]
+            let grouped = self.grapheme_clusters;
+            #[cfg(not(feature = "grapheme"))]
+            let grouped = false;
+            let conflicts = [
+                (self.key.is_some(), "a key"),
+                (self.randomized, "randomized nonces"),
+                (self.tweak_mode != TweakMode::None, "a tweak mode"),
+                (self.compressed, "compression"),
+                (self.preserve_lines, "line preservation"),
+                (grouped, "grapheme grouping"),
+            ];
+            if let Some((_, option)) = conflicts.iter().find(|(set, _)| *set) {
+                panic!("Order-preserving tokens cannot be combined with {option}");
+            }
+        }
+        #[cfg(feature = "ff1")]
+        let ff1 = self.build_ff1();
+        // FF1 keys AES with the user key, so the user key keys nothing else:
+        // the `Cipher` of FF1 instances is unkeyed, as it never writes text,
+        // and the MAC key comes from the standard KDF.
+        #[cfg(feature = "ff1")]
+        let cipher_key = self.key.as_ref().filter(|_| ff1.is_none());
+        #[cfg(not(feature = "ff1"))]
+        let cipher_key = self.key.as_ref();
+        let converter = Converter::new("0123456789", ALPHABET);
+        let rev_converter = converter.inverse();
+        let cipher = match cipher_key {
+            Some(key) => {
+                let alphabet = Zeroizing::new(key::keyed_alphabet(ALPHABET, key.as_bytes()));
+                Cipher::new(&alphabet)
+            }
+            None => Cipher::new(ALPHABET),
+        };
+        let mac_key = self.key.as_ref().filter(|_| self.authenticated).map(|key| {
+            #[cfg(feature = "ff1")]
+            if let Some(ff1) = &ff1 {
+                return MacKey::derive(&ff1.derive_key(b"uniaz mac", 32));
+            }
+            MacKey::derive(key.as_bytes())
+        });
+        #[cfg(feature = "ff1")]
+        let ff1 = ff1.map(|ff1| Box::new(ff1) as BoxedTokenCipher);
+        #[cfg(not(feature = "ff1"))]
+        let ff1 = None;
+
+        UniAz {
+            converter,
+            rev_converter,
+            cipher,
+            min_id_length: self.min_id_length,
+            blocklist: self.blocklist,
+            randomized: self.randomized,
+            tweak_mode: self.tweak_mode,
+            mac_key,
+            key_id: self.key_id,
+            envelope: self.envelope || self.key.is_some(),
+            max_token_length: self
+                .max_token_length
+                .unwrap_or_else(|| digits(char::MAX as u64, ALPHABET.len() as u64)),
+            max_expanded_length: self.max_expanded_length.unwrap_or(MAX_EXPANDED_LENGTH),
+            lenient: self.lenient,
+            #[cfg(feature = "normalization")]
+            normalization: self.normalization,
+            #[cfg(feature = "grapheme")]
+            grapheme_clusters: self.grapheme_clusters,
+            preserve_lines: self.preserve_lines,
+            token_encoding: self.token_encoding,
+            frequency_table: self.frequency_table,
+            compressed: self.compressed,
+            rounds,
+            ff1,
+        }
+    }
+
+    /// Creates the FF1 cipher, if enabled, checking the options it needs.
+    #[cfg(feature = "ff1")]
+    fn build_ff1(&self) -> Option<Ff1> {
+        if !self.ff1 {
+            return None;
+        }
+        let Some(key) = &self.key else {
+            panic!("FF1 requires a key");
+        };
+        let ff1 = Ff1::new(key.as_bytes(), ALPHABET).unwrap_or_else(|e| panic!("{e}"));
+        if self.token_encoding == TokenEncoding::Compact {
+            panic!("FF1 cannot be combined with compact tokens");
+        }
+        if self.compressed {
+            panic!("FF1 cannot be combined with compression");
+        }
+        // Blocklist retries lengthen the longest id token by up to one letter each.
+        let longest_id = self.min_id_length.max(U64_WIDTH) + BLOCKLIST_MAX_ATTEMPTS;
+        if longest_id > ff1.max_len() {
+            panic!(
+                "FF1 tokens are at most {} letters long, so min_id_length must be at most {}",
+                ff1.max_len(),
+                ff1.max_len() - BLOCKLIST_MAX_ATTEMPTS
+            );
+        }
+        Some(ff1)
+    }
+}
+
+/// Number of base-`radix` digits needed to write `value`.
+fn digits(mut value: u64, radix: u64) -> usize {
+    let mut count = 1;
+    while value >= radix {
+        value /= radix;
+        count += 1;
+    }
+    count
+}
@@ -1,4 +1,11 @@
+use crate::ct;
+use subtle::{ConditionallySelectable, ConstantTimeEq};
+use zeroize::Zeroize;
+
 /// Structure for performing multi-base encryption and decryption.
+///
+/// Encryption is a length-preserving permutation of strings over the
+/// alphabet. Characters outside the alphabet are copied through unchanged.
 pub struct Cipher {
     /// The character set (alphabet) used for the base.
     alphabet: Vec<char>,
@@ -10,12 +17,24 @@ pub struct Cipher {
 
 impl Cipher {
     /// Creates a new Cipher and pre-calculates the character-to-value lookup table.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the alphabet is not valid; see [`try_new`](Self::try_new).
     pub fn new(pattern: &str) -> Self {
+        Self::try_new(pattern).unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Creates a new Cipher, or reports why the alphabet cannot be used.
+    ///
+    /// The alphabet must be non-empty, consist of distinct characters, and
+    /// only contain characters below U+0100 so that they fit the lookup table.
+    pub fn try_new(pattern: &str) -> Result<Self, AlphabetError> {
         let alphabet: Vec<char> = pattern.chars().collect();
         let radix = alphabet.len() as u64;
 
         if radix == 0 {
-            panic!("Alphabet cannot be empty");
+            return Err(AlphabetError::Empty);
         }
 
         // Initialize the array with a sentinel value (u64::MAX) indicating "not in alphabet".
@@ -23,113 +42,120 @@ impl Cipher {
 
         // Populate the array by mapping characters to their index value.
         for (i, &c) in alphabet.iter().enumerate() {
-            let index = c as usize;
-            if index >= 256 {
-                panic!("Character set contains non-ASCII characters that exceed the 256 lookup limit.");
+            let slot = val_map_array
+                .get_mut(c as usize)
+                .ok_or(AlphabetError::OutOfRange(c))?;
+            if *slot != u64::MAX {
+                return Err(AlphabetError::Duplicate(c));
             }
-            val_map_array[index] = i as u64;
+            *slot = i as u64;
         }
 
-        Cipher { alphabet, radix, val_map_array }
+        Ok(Cipher { alphabet, radix, val_map_array })
     }
 
-    /// Helper function: Retrieves the index (value) of a character in the alphabet (O(1) lookup).
-    fn char_to_val(&self, c: char) -> u64 {
-        let index = c as usize;
-        if index >= 256 {
-            panic!("Non-ASCII character encountered during encryption/decryption.");
-        }
-
-        let val = self.val_map_array[index];
-
-        if val == u64::MAX {
-            panic!("Input string contains character not defined in the alphabet.");
-        }
-        val
+    /// The alphabet, in the order that defines digit values.
+    #[cfg_attr(not(feature = "analysis"), allow(dead_code))]
+    pub(crate) fn alphabet(&self) -> &[char] {
+        &self.alphabet
     }
 
-    /// Core helper function: Calculates the large number modulus based on input digits.
-    /// This result is used as a seed for disordering/shifting.
-    fn get_seed_mod(&self, digits: &[char], skip_idx: usize, modulus: u64) -> usize {
-        if modulus == 0 { return 0; }
-
-        let mut remainder: u64 = 0;
-        let mut is_empty = true;
-
+    /// Core helper function: Reads the input digits, except the one at `skip_idx`, as a number.
+    /// Its remainders are used as seeds for disordering/shifting.
+    fn seed<'a>(&'a self, digits: &'a [char], skip_idx: usize) -> Seed<'a> {
+        let mut value: Option<u64> = Some(0);
         for (i, &c) in digits.iter().enumerate() {
             if i == skip_idx { continue; }
-            is_empty = false;
 
-            // O(1) lookup
-            let val = self.char_to_val(c);
+            // Characters outside the alphabet never change, so they are left out of the seed.
+            let Some(val) = self.lookup(c) else { continue };
 
-            remainder = (remainder * self.radix + val) % modulus;
+            value = value
+                .and_then(|v| v.checked_mul(self.radix))
+                .and_then(|v| v.checked_add(val));
         }
-
-        if is_empty { 0 } else { remainder as usize }
+        Seed { cipher: self, digits, skip_idx, value }
     }
 
     /// Generates a disordered replacement table (permutation) based on a seed derived from input digits.
-    /// Returns the permutation and a char->index lookup table for O(1) position queries.
-    fn disorder(&self, digits: &[char], skip_idx: usize) -> (Vec<char>, [u8; 256]) {
-        let mut obj = self.alphabet.clone();
+    /// Returns the permutation as alphabet indices; only the first `radix` entries are used.
+    ///
+    /// The seed reads digits through the keyed lookup table, so under a key
+    /// the permutation depends on the key. It is not wiped, and neither are
+    /// the seeds and the intermediate digits of each round: key-dependent
+    /// values stay in freed memory. Only the alphabet and its lookup table
+    /// are wiped (see [`wipe`](Self::wipe)).
+    fn disorder(&self, seed: &Seed, tweak: u64) -> [u8; 256] {
+        let len = self.alphabet.len();
+        let mut obj = [0u8; 256];
+        for (i, slot) in obj.iter_mut().enumerate().take(len) {
+            *slot = i as u8;
+        }
 
-        for i in (1..obj.len()).rev() {
+        for i in (1..len).rev() {
             let current_length = (i + 1) as u64;
-            let j = self.get_seed_mod(digits, skip_idx, current_length);
+            let j = seed.modulo(current_length, tweak);
             obj.swap(i, j);
         }
+        obj
+    }
 
-        // Build O(1) char->index lookup for the permutation.
-        let mut pos_map = [255u8; 256];
-        for (idx, &c) in obj.iter().enumerate() {
-            pos_map[c as usize] = idx as u8;
-        }
-        (obj, pos_map)
+    /// Helper function: Finds the position of an alphabet value within a permutation.
+    fn position(&self, capacity: &[u8; 256], val: u64) -> u64 {
+        capacity[..self.alphabet.len()]
+            .iter()
+            .position(|&v| u64::from(v) == val)
+            .expect("permutation contains every alphabet value") as u64
+    }
+
+    /// Helper function: Looks up the value of a character, or `None` if it is not in the alphabet.
+    fn lookup(&self, c: char) -> Option<u64> {
+        let val = *self.val_map_array.get(c as usize)?;
+        (val != u64::MAX).then_some(val)
     }
 
     /// Single-iteration forward encryption function.
-    fn encrypt_once(&self, input: &str) -> String {
+    fn encrypt_once(&self, input: &str, tweak: u64) -> String {
         let mut digit_list: Vec<char> = input.chars().collect();
         let len = digit_list.len();
 
         for i in 0..len {
             let current_char = digit_list[i];
-            let (capacity, pos_map) = self.disorder(&digit_list, i);
-            let seed_mod_radix = self.get_seed_mod(&digit_list, i, self.radix);
+            let seed = self.seed(&digit_list, i);
+            let capacity = self.disorder(&seed, tweak);
+            let seed_mod_radix = seed.modulo(self.radix, tweak);
             let offset = seed_mod_radix as u64 + (i * i) as u64 + 1;
 
-            let pos = pos_map[current_char as usize];
-            if pos != 255 {
-                let new_pos = ((pos as u64 + offset) % self.radix) as usize;
-                digit_list[i] = capacity[new_pos];
+            // Characters outside the alphabet are left in place.
+            if let Some(val) = self.lookup(current_char) {
+                let pos = self.position(&capacity, val);
+                let new_pos = ((pos + offset) % self.radix) as usize;
+                digit_list[i] = self.alphabet[capacity[new_pos] as usize];
             }
         }
         digit_list.into_iter().collect()
     }
 
     /// Single-iteration backward decryption function.
-    fn decrypt_once(&self, input: &str) -> String {
+    ///
+    /// Walks the positions in reverse and shifts within the reversed permutation,
+    /// which undoes the forward shift.
+    fn decrypt_once(&self, input: &str, tweak: u64) -> String {
         let mut digit_list: Vec<char> = input.chars().collect();
         let len = digit_list.len();
+        let last = self.radix - 1;
 
         for i in (0..len).rev() {
             let current_char = digit_list[i];
-            let (mut capacity, mut pos_map) = self.disorder(&digit_list, i);
-            capacity.reverse();
-
-            // Rebuild pos_map for reversed permutation.
-            for (idx, &c) in capacity.iter().enumerate() {
-                pos_map[c as usize] = idx as u8;
-            }
-
-            let seed_mod_radix = self.get_seed_mod(&digit_list, i, self.radix);
+            let seed = self.seed(&digit_list, i);
+            let capacity = self.disorder(&seed, tweak);
+            let seed_mod_radix = seed.modulo(self.radix, tweak);
             let offset = seed_mod_radix as u64 + (i * i) as u64 + 1;
 
-            let pos = pos_map[current_char as usize];
-            if pos != 255 {
-                let new_pos = ((pos as u64 + offset) % self.radix) as usize;
-                digit_list[i] = capacity[new_pos];
+            if let Some(val) = self.lookup(current_char) {
+                let pos = last - self.position(&capacity, val);
+                let new_pos = (pos + offset) % self.radix;
+                digit_list[i] = self.alphabet[capacity[(last - new_pos) as usize] as usize];
             }
         }
         digit_list.into_iter().collect()
@@ -137,21 +163,214 @@ impl Cipher {
 
     /// Encrypts the input string for a specified number of iterations.
     pub fn encrypt(&self, input: &str, iteration: usize) -> String {
+        self.encrypt_tweaked(input, iteration, 0)
+    }
+
+    /// Decrypts the input string for a specified number of iterations.
+    pub fn decrypt(&self, input: &str, iteration: usize) -> String {
+        self.decrypt_tweaked(input, iteration, 0)
+    }
+
+    /// Encrypts the input string with a public tweak mixed into every seed.
+    ///
+    /// Different tweaks select different permutations for the same input; the
+    /// same tweak must be passed to [`decrypt_tweaked`](Self::decrypt_tweaked).
+    /// A tweak of 0 is equivalent to [`encrypt`](Self::encrypt).
+    pub fn encrypt_tweaked(&self, input: &str, iteration: usize, tweak: u64) -> String {
         let mut res = String::from(input);
         for _ in 0..iteration {
-            res = self.encrypt_once(&res);
+            res = self.encrypt_once(&res, tweak);
         }
         res
     }
 
-    /// Decrypts the input string for a specified number of iterations.
-    pub fn decrypt(&self, input: &str, iteration: usize) -> String {
+    /// Decrypts the input string with the tweak it was encrypted with.
+    pub fn decrypt_tweaked(&self, input: &str, iteration: usize, tweak: u64) -> String {
         let mut res = String::from(input);
         for _ in 0..iteration {
-            res = self.decrypt_once(&res);
+            res = self.decrypt_once(&res, tweak);
         }
         res
     }
+
+    /// Decrypts like [`decrypt_tweaked`](Self::decrypt_tweaked), in time that
+    /// does not depend on the alphabet order or the decrypted values.
+    ///
+    /// Only the input length, the positions of characters outside the
+    /// alphabet (which the cipher never moves), the iteration count and the
+    /// tweak affect the timing: every table lookup scans the whole table,
+    /// and secret values are reduced without branches or hardware division.
+    /// It is several times slower than [`decrypt_tweaked`](Self::decrypt_tweaked).
+    /// With alphabets mixing ASCII and other characters, the UTF-8 length of
+    /// the output still depends on the decrypted characters.
+    pub fn decrypt_ct(&self, input: &str, iteration: usize, tweak: u64) -> String {
+        // The input is the public ciphertext, so it may index the lookup table.
+        let mut values: Vec<Option<u8>> =
+            input.chars().map(|c| self.lookup(c).map(|v| v as u8)).collect();
+        for _ in 0..iteration {
+            self.decrypt_once_ct(&mut values, tweak);
+        }
+        input
+            .chars()
+            .zip(values)
+            .map(|(c, value)| match value {
+                Some(value) => {
+                    let mut code = 0u32;
+                    for (k, &a) in self.alphabet.iter().enumerate() {
+                        code.conditional_assign(&(a as u32), (k as u8).ct_eq(&value));
+                    }
+                    char::from_u32(code).expect("alphabet characters are valid")
+                }
+                None => c,
+            })
+            .collect()
+    }
+
+    /// Constant-time counterpart of [`decrypt_once`](Self::decrypt_once) on
+    /// alphabet values.
+    fn decrypt_once_ct(&self, values: &mut [Option<u8>], tweak: u64) {
+        let len = self.alphabet.len();
+        let last = self.radix - 1;
+
+        for i in (0..values.len()).rev() {
+            // Characters outside the alphabet are left in place.
+            let Some(val) = values[i] else { continue };
+            let modulo = |modulus: u64| self.seed_mod_ct(values, i, modulus, tweak);
+
+            // Same Fisher-Yates pass as `disorder`, swapping through a full scan.
+            let mut capacity = [0u8; 256];
+            for (k, slot) in capacity.iter_mut().enumerate().take(len) {
+                *slot = k as u8;
+            }
+            for k in (1..len).rev() {
+                let j = modulo((k + 1) as u64);
+                for m in 0..=k {
+                    let hit = (m as u64).ct_eq(&j);
+                    let (x, y) = (capacity[k], capacity[m]);
+                    capacity[k] = u8::conditional_select(&x, &y, hit);
+                    capacity[m] = u8::conditional_select(&y, &x, hit);
+                }
+            }
+
+            let mut pos = 0u64;
+            for (k, &v) in capacity[..len].iter().enumerate() {
+                pos.conditional_assign(&(k as u64), v.ct_eq(&val));
+            }
+            // `(pos + offset) % radix`, split into sums below twice the radix.
+            let pos = last - pos;
+            let public_offset = ((i * i) as u64 + 1) % self.radix;
+            let new_pos = ct::subtract(pos + modulo(self.radix), self.radix);
+            let new_pos = ct::subtract(new_pos + public_offset, self.radix);
+
+            let index = last - new_pos;
+            let mut value = 0u8;
+            for (k, &v) in capacity[..len].iter().enumerate() {
+                value.conditional_assign(&v, (k as u64).ct_eq(&index));
+            }
+            values[i] = Some(value);
+        }
+    }
+
+    /// Constant-time counterpart of [`Seed::modulo`] on alphabet values.
+    fn seed_mod_ct(&self, values: &[Option<u8>], skip_idx: usize, modulus: u64, tweak: u64) -> u64 {
+        let mut remainder = 0;
+        for (i, value) in values.iter().enumerate() {
+            if i == skip_idx { continue; }
+            let Some(value) = value else { continue };
+            remainder = ct::reduce(remainder * self.radix + u64::from(*value), modulus);
+        }
+        // The tweak is public.
+        ct::subtract(remainder + tweak % modulus, modulus)
+    }
+}
+
+impl Cipher {
+    /// Wipes the alphabet and the lookup table, leaving the cipher unusable.
+    fn wipe(&mut self) {
+        // A keyed alphabet is a permutation derived from the key, and the
+        // lookup table is its inverse.
+        self.alphabet.zeroize();
+        self.val_map_array.zeroize();
+    }
+}
+
+impl Drop for Cipher {
+    fn drop(&mut self) {
+        self.wipe();
+    }
+}
+
+impl std::fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The alphabet order may be key material, so only its size is shown.
+        f.debug_struct("Cipher").field("radix", &self.radix).finish_non_exhaustive()
+    }
+}
+
+/// Error returned by [`Cipher::try_new`] for an unusable alphabet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphabetError {
+    /// The alphabet has no characters.
+    Empty,
+    /// The character is U+0100 or above and does not fit the lookup table.
+    OutOfRange(char),
+    /// The character occurs more than once.
+    Duplicate(char),
+}
+
+impl std::fmt::Display for AlphabetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlphabetError::Empty => write!(f, "Alphabet cannot be empty"),
+            AlphabetError::OutOfRange(c) => {
+                write!(f, "Character set contains {c:?}, which exceeds the 256 lookup limit")
+            }
+            AlphabetError::Duplicate(c) => write!(f, "Character set contains {c:?} more than once"),
+        }
+    }
+}
+
+impl std::error::Error for AlphabetError {}
+
+/// The input digits other than one skipped position, read as a base-`radix` number.
+struct Seed<'a> {
+    cipher: &'a Cipher,
+    digits: &'a [char],
+    skip_idx: usize,
+    /// The exact number, when it fits in a `u64`.
+    value: Option<u64>,
+}
+
+impl Seed<'_> {
+    /// Calculates the number modulo `modulus`, plus the tweak.
+    ///
+    /// Short inputs take a single division; longer ones are reduced digit by digit.
+    /// The tweak is added after reduction, so a tweak of 0 leaves the seed unchanged.
+    fn modulo(&self, modulus: u64, tweak: u64) -> usize {
+        if modulus == 0 { return 0; }
+
+        let remainder = match self.value {
+            // 32-bit division is markedly faster and covers every character token.
+            Some(value) if value <= u64::from(u32::MAX) && modulus <= u64::from(u32::MAX) => {
+                u64::from(value as u32 % modulus as u32)
+            }
+            Some(value) => value % modulus,
+            None => {
+                let mut remainder: u64 = 0;
+                for (i, &c) in self.digits.iter().enumerate() {
+                    if i == self.skip_idx { continue; }
+                    let Some(val) = self.cipher.lookup(c) else { continue };
+                    remainder = (remainder * self.cipher.radix + val) % modulus;
+                }
+                remainder
+            }
+        };
+
+        // Both terms are below the modulus, so one conditional subtraction reduces the sum.
+        let tweak = if tweak == 0 { 0 } else { tweak % modulus };
+        let sum = remainder + tweak;
+        (if sum >= modulus { sum - modulus } else { sum }) as usize
+    }
 }
 
 // ---------------- Test Section ----------------
@@ -166,8 +385,8 @@ mod tests {
         // Standard decimal test
         let cipher = Cipher::new("0123456789");
         let original = "1234567890";
-        let encrypted = cipher.encrypt_once(original);
-        let decrypted = cipher.decrypt_once(&encrypted);
+        let encrypted = cipher.encrypt_once(original, 0);
+        let decrypted = cipher.decrypt_once(&encrypted, 0);
 
         println!("Decimal - Original: {}", original);
         println!("Decimal - Encrypted: {}", encrypted);
@@ -182,8 +401,8 @@ mod tests {
         // Hexadecimal test
         let cipher = Cipher::new("0123456789ABCDEF");
         let original = "A1F90";
-        let encrypted = cipher.encrypt_once(original);
-        let decrypted = cipher.decrypt_once(&encrypted);
+        let encrypted = cipher.encrypt_once(original, 0);
+        let decrypted = cipher.decrypt_once(&encrypted, 0);
 
         println!("Hex - Original: {}", original);
         println!("Hex - Encrypted: {}", encrypted);
@@ -198,8 +417,8 @@ mod tests {
         let cipher = Cipher::new(base62_pattern);
 
         let original = "HelloRust2025";
-        let encrypted = cipher.encrypt_once(original);
-        let decrypted = cipher.decrypt_once(&encrypted);
+        let encrypted = cipher.encrypt_once(original, 0);
+        let decrypted = cipher.decrypt_once(&encrypted, 0);
 
         println!("Base62 - Original: {}", original);
         println!("Base62 - Encrypted: {}", encrypted);
@@ -212,8 +431,8 @@ mod tests {
         // Binary test (only 0 and 1)
         let cipher = Cipher::new("01");
         let original = "110101011100";
-        let encrypted = cipher.encrypt_once(original);
-        let decrypted = cipher.decrypt_once(&encrypted);
+        let encrypted = cipher.encrypt_once(original, 0);
+        let decrypted = cipher.decrypt_once(&encrypted, 0);
         println!("Binary - Original: {}", original);
         println!("Binary - Encrypted: {}", encrypted);
         assert_eq!(original, &decrypted);
@@ -239,7 +458,7 @@ mod tests {
         for i in 0..50000 {
             let mut res = format!("{i:05}");
             for _ in 0..2 {
-                res = c.encrypt_once(&res);
+                res = c.encrypt_once(&res, 0);
             }
             print!("{}, ", u32::from_str(&res).unwrap());
             let initial = res.chars().next().unwrap();
@@ -250,6 +469,11 @@ mod tests {
         println!();
         println!("{:?}", flag);
         println!("{:?}", avg);
+
+        // Chi-square with 9 degrees of freedom; 27.9 is the 0.1% critical value.
+        let expected = 50000.0 / 10.0;
+        let chi_square: f64 = flag.values().map(|&o| (o as f64 - expected).powi(2) / expected).sum();
+        assert!(chi_square < 27.9, "first digits are not uniform: {chi_square}");
     }
 
     #[test]
@@ -261,10 +485,10 @@ mod tests {
         for i in "你好世界".chars() {
             let original = converter.convert(&(i as u32).to_string()).unwrap();
             println!("{} {:?}", i, original);
-            let encrypted = cipher.encrypt_once(&original);
-            let encrypted = cipher.encrypt_once(&encrypted);
-            let decrypted = cipher.decrypt_once(&encrypted);
-            let decrypted = cipher.decrypt_once(&decrypted);
+            let encrypted = cipher.encrypt_once(&original, 0);
+            let encrypted = cipher.encrypt_once(&encrypted, 0);
+            let decrypted = cipher.decrypt_once(&encrypted, 0);
+            let decrypted = cipher.decrypt_once(&decrypted, 0);
             println!("Alphabet - Original: {}", original);
             println!("Alphabet - Encrypted: {}", encrypted);
             println!("Alphabet - Decrypted: {}", decrypted);
@@ -287,4 +511,123 @@ mod tests {
             println!("Alphabet - Decrypted: {}", decrypted);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_tweak() {
+        let cipher = Cipher::new("abcdefghijklmnopqrstuvwxyz");
+        let original = "abpx";
+        assert_eq!(cipher.encrypt_tweaked(original, 2, 0), cipher.encrypt(original, 2));
+
+        let outputs: std::collections::HashSet<_> = (0..64)
+            .map(|tweak| {
+                let encrypted = cipher.encrypt_tweaked(original, 2, tweak);
+                assert_eq!(cipher.decrypt_tweaked(&encrypted, 2, tweak), original);
+                encrypted
+            })
+            .collect();
+        assert!(outputs.len() > 32);
+
+        let single = cipher.encrypt_tweaked("q", 2, 7);
+        assert_eq!(cipher.decrypt_tweaked(&single, 2, 7), "q");
+    }
+
+    #[test]
+    fn test_try_new() {
+        assert!(Cipher::try_new("01").is_ok());
+        assert_eq!(Cipher::try_new("").err(), Some(AlphabetError::Empty));
+        assert_eq!(Cipher::try_new("ab你").err(), Some(AlphabetError::OutOfRange('你')));
+        assert_eq!(Cipher::try_new("abca").err(), Some(AlphabetError::Duplicate('a')));
+    }
+
+    #[test]
+    fn test_unknown_characters() {
+        let cipher = Cipher::new("abcdefghijklmnopqrstuvwxyz");
+        let original = "hello, 世界 world!";
+        let encrypted = cipher.encrypt(original, 2);
+        assert_eq!(cipher.decrypt(&encrypted, 2), original);
+        for (a, b) in original.chars().zip(encrypted.chars()) {
+            assert_eq!(a.is_ascii_lowercase(), b.is_ascii_lowercase());
+            if !a.is_ascii_lowercase() {
+                assert_eq!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_zeroize_on_drop() {
+        use crate::UniAz;
+        use std::mem::MaybeUninit;
+
+        let cipher = Cipher::new("qwertyuiopasdfghjklzxcvbnm");
+        assert_eq!(format!("{cipher:?}"), "Cipher { radix: 26, .. }");
+        let mut slot = MaybeUninit::new(cipher);
+        // Dropping in place leaves the inline lookup table in the slot, so
+        // its bytes can still be read; the alphabet buffer is freed.
+        let table = unsafe {
+            slot.as_mut_ptr().drop_in_place();
+            std::ptr::addr_of!((*slot.as_ptr()).val_map_array).read()
+        };
+        assert!(table.iter().all(|&v| v == 0));
+
+        let u = UniAz::builder().key(b"secret").build();
+        assert!(u.cipher.val_map_array.iter().any(|&v| v != 0 && v != u64::MAX));
+        let mut slot = MaybeUninit::new(u);
+        let table = unsafe {
+            slot.as_mut_ptr().drop_in_place();
+            std::ptr::addr_of!((*slot.as_ptr()).cipher.val_map_array).read()
+        };
+        assert!(table.iter().all(|&v| v == 0));
+    }
+
+    mod properties {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// A random alphabet of distinct characters from the 256-entry lookup
+        /// range, together with an input drawn from it.
+        fn alphabet_and_input() -> impl Strategy<Value = (String, String)> {
+            prop::collection::btree_set(any::<u8>(), 1..=256)
+                .prop_map(|set| set.into_iter().map(char::from).collect::<Vec<_>>())
+                .prop_shuffle()
+                .prop_flat_map(|alphabet| {
+                    let input = prop::collection::vec(0..alphabet.len(), 0..48);
+                    (Just(alphabet), input)
+                })
+                .prop_map(|(alphabet, input)| {
+                    let text = input.iter().map(|&i| alphabet[i]).collect();
+                    (alphabet.into_iter().collect(), text)
+                })
+        }
+
+        proptest! {
+            #[test]
+            fn roundtrip((alphabet, input) in alphabet_and_input(), iteration in 0usize..6, tweak in any::<u64>()) {
+                let cipher = Cipher::new(&alphabet);
+                let encrypted = cipher.encrypt_tweaked(&input, iteration, tweak);
+                prop_assert_eq!(cipher.decrypt_tweaked(&encrypted, iteration, tweak), input.clone());
+                prop_assert_eq!(cipher.decrypt(&cipher.encrypt(&input, iteration), iteration), input.clone());
+            }
+
+            #[test]
+            fn length_and_alphabet_preserved((alphabet, input) in alphabet_and_input(), iteration in 0usize..6) {
+                let cipher = Cipher::new(&alphabet);
+                let encrypted = cipher.encrypt(&input, iteration);
+                prop_assert_eq!(encrypted.chars().count(), input.chars().count());
+                prop_assert!(encrypted.chars().all(|c| alphabet.contains(c)), "{:?} left the alphabet", encrypted);
+            }
+        }
+
+        proptest! {
+            // The constant-time path scans whole tables, so fewer cases keep it affordable.
+            #![proptest_config(ProptestConfig::with_cases(64))]
+
+            #[test]
+            fn constant_time_matches((alphabet, input) in alphabet_and_input(), iteration in 0usize..4, tweak in any::<u64>()) {
+                let cipher = Cipher::new(&alphabet);
+                // '€' is never in the alphabet, so it passes through.
+                let input = format!("{input}€{input}");
+                prop_assert_eq!(cipher.decrypt_ct(&input, iteration, tweak), cipher.decrypt_tweaked(&input, iteration, tweak));
+            }
+        }
+    }
+}
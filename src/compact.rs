@@ -0,0 +1,286 @@
+use crate::{DecryptError, ALPHABET};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Source of the bundled table, see [`FrequencyTable::bundled`].
+const BUNDLED: &str = include_str!("../data/frequency.txt");
+
+/// Number of Unicode scalar values, i.e. code points minus surrogates.
+const SCALAR_COUNT: u32 = 0x110000 - 0x800;
+
+/// Characters ranked by frequency for [`TokenEncoding::Compact`](crate::TokenEncoding::Compact).
+///
+/// The character of rank `r` gets the `r`-th shortest token: the 26 most
+/// frequent characters get 1 letter, the next 676 get 2 letters, and so on.
+/// Characters missing from the table rank after all listed ones, in code
+/// point order, so every scalar value still has a token.
+///
+/// Text must be decrypted with the same table it was encrypted with.
+///
+/// # Examples
+///
+/// ```
+/// use uniaz::{FrequencyTable, TokenEncoding, UniAz};
+///
+/// let table = FrequencyTable::from_corpus("aaab ab b");
+/// let uni_az = UniAz::builder()
+///     .token_encoding(TokenEncoding::Compact)
+///     .frequency_table(table)
+///     .build();
+/// assert_eq!(uni_az.encrypt('a').len(), 1);
+/// assert_eq!(uni_az.decrypt_str(&uni_az.encrypt_str("abc")).unwrap(), "abc");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrequencyTable {
+    /// Listed characters, most frequent first.
+    chars: Vec<char>,
+    /// Rank of each listed character.
+    ranks: HashMap<char, u32>,
+    /// Scalar indices of the listed characters, ascending.
+    sorted: Vec<u32>,
+}
+
+impl FrequencyTable {
+    /// Creates a table from characters ordered from most to least frequent.
+    ///
+    /// Repeated characters keep their first position.
+    pub fn new<I: IntoIterator<Item = char>>(chars: I) -> Self {
+        let mut table = FrequencyTable {
+            chars: Vec::new(),
+            ranks: HashMap::new(),
+            sorted: Vec::new(),
+        };
+        for c in chars {
+            if !table.ranks.contains_key(&c) {
+                table.ranks.insert(c, table.chars.len() as u32);
+                table.chars.push(c);
+                table.sorted.push(scalar_index(c));
+            }
+        }
+        table.sorted.sort_unstable();
+        table
+    }
+
+    /// Creates a table by counting the characters of a sample text.
+    ///
+    /// Ties are broken by code point, so the same corpus always yields the
+    /// same table.
+    pub fn from_corpus(corpus: &str) -> Self {
+        let mut counts: HashMap<char, usize> = HashMap::new();
+        for c in corpus.chars() {
+            *counts.entry(c).or_default() += 1;
+        }
+        let mut chars: Vec<(char, usize)> = counts.into_iter().collect();
+        chars.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        Self::new(chars.into_iter().map(|(c, _)| c))
+    }
+
+    /// Returns the table bundled with the crate.
+    ///
+    /// It ranks about 2,000 characters common in Chinese and English text,
+    /// so frequent characters of either language get 1-2 letter tokens and
+    /// every listed character at most 3. Its order is part of the compact
+    /// format and never changes.
+    pub fn bundled() -> Self {
+        Self::bundled_ref().clone()
+    }
+
+    /// Shared instance of the bundled table.
+    pub(crate) fn bundled_ref() -> &'static Self {
+        static TABLE: OnceLock<FrequencyTable> = OnceLock::new();
+        TABLE.get_or_init(|| {
+            Self::new(
+                BUNDLED
+                    .lines()
+                    .filter(|line| !line.starts_with('#'))
+                    .flat_map(|line| match parse_code_point(line) {
+                        Some(c) => vec![c],
+                        None => line.chars().filter(|c| !c.is_whitespace()).collect(),
+                    }),
+            )
+        })
+    }
+
+    /// Number of listed characters.
+    pub fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    /// Checks whether no characters are listed.
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    /// Writes the plain (not yet enciphered) token of a character.
+    pub(crate) fn encode(&self, c: char) -> String {
+        let mut rank = u64::from(self.rank(c));
+        let mut len = 1;
+        let mut count = ALPHABET.len() as u64;
+        while rank >= count {
+            rank -= count;
+            count *= ALPHABET.len() as u64;
+            len += 1;
+        }
+
+        let mut letters = vec![b'a'; len];
+        for slot in letters.iter_mut().rev() {
+            *slot = b'a' + (rank % 26) as u8;
+            rank /= 26;
+        }
+        String::from_utf8(letters).expect("letters are ASCII")
+    }
+
+    /// Reads a plain token written by [`encode`](Self::encode).
+    pub(crate) fn decode(&self, letters: &str) -> Result<char, DecryptError> {
+        if letters.is_empty() {
+            return Err(DecryptError::InvalidToken);
+        }
+        // Tokens of up to 5 letters cover all scalar values.
+        if letters.len() > 5 {
+            return Err(DecryptError::TokenTooLong);
+        }
+        // Tokens of every shorter length come first.
+        let mut rank: u64 = 0;
+        let mut count: u64 = 1;
+        for _ in 1..letters.len() {
+            count *= 26;
+            rank += count;
+        }
+        let value = letters.bytes().fold(0, |acc: u64, b| acc * 26 + u64::from(b - b'a'));
+        let rank = u32::try_from(rank + value).map_err(|_| DecryptError::InvalidCodepoint)?;
+        self.char_at(rank).ok_or(DecryptError::InvalidCodepoint)
+    }
+
+    /// Rank of a character: its table position, or its position among the
+    /// unlisted characters after all listed ones.
+    pub(crate) fn rank(&self, c: char) -> u32 {
+        if let Some(&rank) = self.ranks.get(&c) {
+            return rank;
+        }
+        let index = scalar_index(c);
+        let listed_below = self.sorted.partition_point(|&s| s < index) as u32;
+        self.chars.len() as u32 + index - listed_below
+    }
+
+    /// Inverse of [`rank`](Self::rank).
+    pub(crate) fn char_at(&self, rank: u32) -> Option<char> {
+        if let Some(&c) = self.chars.get(rank as usize) {
+            return Some(c);
+        }
+        // The k-th unlisted scalar is k plus the number of listed scalars
+        // below it. Below the listed scalar at position i lie `sorted[i] - i`
+        // unlisted ones, which grows with i, so binary search finds that number.
+        let k = rank - self.chars.len() as u32;
+        let (mut low, mut high) = (0, self.sorted.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.sorted[mid] - mid as u32 <= k {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let index = k + low as u32;
+        if index >= SCALAR_COUNT {
+            return None;
+        }
+        let code_point = if index >= 0xD800 { index + 0x800 } else { index };
+        char::from_u32(code_point)
+    }
+}
+
+/// Position of a character among all scalar values, skipping surrogates.
+fn scalar_index(c: char) -> u32 {
+    let code_point = c as u32;
+    if code_point >= 0xE000 { code_point - 0x800 } else { code_point }
+}
+
+/// Parses a `U+XXXX` line of the bundled table.
+fn parse_code_point(line: &str) -> Option<char> {
+    let hex = line.trim().strip_prefix("U+")?;
+    char::from_u32(u32::from_str_radix(hex, 16).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TokenEncoding, UniAz};
+
+    #[test]
+    fn test_bundled() {
+        let table = FrequencyTable::bundled();
+        assert!(table.len() > 2000);
+        assert_eq!(table.chars[..2], [' ', 'e']);
+        assert_eq!(table.rank('\n'), 18);
+        assert!(table.ranks.contains_key(&'\t'));
+        assert_eq!(table.encode(' '), "a");
+        assert_eq!(table.encode('的'), "h");
+    }
+
+    #[test]
+    fn test_rank_bijection() {
+        let table = FrequencyTable::new("zy\u{e000}\u{d7ff}你a".chars());
+        for rank in (0..70_000).chain(SCALAR_COUNT - 10..SCALAR_COUNT) {
+            let c = table.char_at(rank).unwrap();
+            assert_eq!(table.rank(c), rank);
+            assert_eq!(table.decode(&table.encode(c)), Ok(c));
+        }
+        assert_eq!(table.char_at(SCALAR_COUNT), None);
+        assert_eq!(table.decode("zzzzz"), Err(DecryptError::InvalidCodepoint));
+        assert_eq!(table.decode("aaaaaa"), Err(DecryptError::TokenTooLong));
+        assert_eq!(table.decode("zzzzzz"), Err(DecryptError::TokenTooLong));
+        assert_eq!(table.encode('z'), "a");
+        assert_eq!(table.encode('a'), "f");
+        assert_eq!(table.encode('\0'), "g");
+    }
+
+    #[test]
+    fn test_from_corpus() {
+        let table = FrequencyTable::from_corpus("abcabcaab");
+        assert_eq!(table.chars, ['a', 'b', 'c']);
+        assert!(FrequencyTable::from_corpus("").is_empty());
+    }
+
+    #[test]
+    fn test_size_reduction() {
+        let radix = UniAz::builder().envelope(true).build();
+        let compact = UniAz::builder().token_encoding(TokenEncoding::Compact).build();
+        for corpus in [
+            include_str!("../benches/corpus/zh.txt"),
+            include_str!("../benches/corpus/mixed.txt"),
+        ] {
+            let long = radix.encrypt_str(corpus);
+            let short = compact.encrypt_str(corpus);
+            assert_eq!(compact.decrypt_str(&short).unwrap(), corpus);
+            // Decoding only needs the header.
+            assert_eq!(radix.decrypt_str(&short).unwrap(), corpus);
+            assert!(short.len() * 10 < long.len() * 8, "{} vs {}", short.len(), long.len());
+        }
+        // Raising the token limit does not make longer compact tokens valid.
+        let lax = UniAz::builder().token_encoding(TokenEncoding::Compact).max_token_length(8);
+        assert_eq!(lax.build().decrypt("abcdef"), Err(DecryptError::TokenTooLong));
+    }
+
+    #[test]
+    fn test_self_test_samples() {
+        let table = FrequencyTable::from_corpus("hello world");
+        let u = UniAz::builder()
+            .token_encoding(TokenEncoding::Compact)
+            .frequency_table(table)
+            .build();
+        assert_eq!(u.self_test_range(0..0x4000), Ok(0x4000));
+        assert_eq!(u.self_test_range(0x10_c000..0x11_0000), Ok(0x4000));
+    }
+
+    #[test]
+    #[ignore = "exhaustive over all scalar values; run with `cargo test --release -- --ignored`"]
+    fn test_self_test() {
+        let table = FrequencyTable::from_corpus("hello world");
+        let u = UniAz::builder()
+            .token_encoding(TokenEncoding::Compact)
+            .frequency_table(table)
+            .build();
+        assert_eq!(u.encrypt('l').len(), 1);
+        assert_eq!(u.self_test(), Ok(SCALAR_COUNT as usize));
+    }
+}
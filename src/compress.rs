@@ -0,0 +1,252 @@
+use crate::{DecryptError, FrequencyTable};
+use std::collections::HashMap;
+
+/// Shortest repeat worth a back-reference: a bigram.
+const MIN_MATCH: usize = 2;
+
+/// Longest repeat one back-reference copies, which bounds how much a
+/// hostile stream can expand per letter.
+const MAX_MATCH: usize = 4096;
+
+/// Default limit on the characters one stream expands to.
+pub(crate) const MAX_EXPANDED_LENGTH: usize = 1 << 20;
+
+/// How many earlier occurrences of a bigram are tried as match sources.
+const MAX_CANDIDATES: usize = 32;
+
+/// Number base of stream integers: each letter carries one base-13 digit.
+const BASE: u32 = 13;
+
+/// Compresses text into a letters-only stream.
+///
+/// The stream is a sequence of items, each starting with a number `v`:
+///
+/// - even `v`: `v / 2 + 1` literal characters follow, each written as its
+///   rank in the frequency table;
+/// - odd `v`: a copy of `(v - 1) / 2 + 2` characters follows, written as the
+///   distance back to its source minus one. Sources may overlap the copy,
+///   so a run of one character is a copy at distance 1.
+///
+/// Numbers are written most significant digit first in base 13, with `n-z`
+/// for all digits but the last and `a-m` for the last, so frequent
+/// characters (rank < 13) take a single letter.
+pub(crate) fn compress(text: &str, table: &FrequencyTable) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::new();
+    let mut literals: Vec<char> = Vec::new();
+    let mut sources: HashMap<(char, char), Vec<usize>> = HashMap::new();
+
+    let mut i = 0;
+    while i < chars.len() {
+        let found = best_match(&chars, i, &sources).filter(|&(len, distance)| {
+            match_cost(len, distance) < literal_cost(&chars[i..i + len], table)
+        });
+        let step = match found {
+            Some((len, distance)) => {
+                flush_literals(&mut out, &mut literals, table);
+                push_number(&mut out, (((len - MIN_MATCH) * 2) + 1) as u32);
+                push_number(&mut out, (distance - 1) as u32);
+                len
+            }
+            None => {
+                literals.push(chars[i]);
+                1
+            }
+        };
+        for start in i..i + step {
+            if let Some(&next) = chars.get(start + 1) {
+                sources.entry((chars[start], next)).or_default().push(start);
+            }
+        }
+        i += step;
+    }
+    flush_literals(&mut out, &mut literals, table);
+    out
+}
+
+/// Expands a stream written by [`compress`].
+///
+/// Fails with [`DecryptError::TokenTooLong`] once the text would exceed
+/// `limit` characters.
+pub(crate) fn expand(
+    stream: &str,
+    table: &FrequencyTable,
+    limit: usize,
+) -> Result<String, DecryptError> {
+    let mut digits = stream.bytes().map(|b| u32::from(b - b'a'));
+    let mut out: Vec<char> = Vec::new();
+    while let Some(value) = read_number(&mut digits)? {
+        if value % 2 == 0 {
+            for _ in 0..=value / 2 {
+                if out.len() == limit {
+                    return Err(DecryptError::TokenTooLong);
+                }
+                let rank = read_number(&mut digits)?.ok_or(DecryptError::InvalidToken)?;
+                out.push(table.char_at(rank).ok_or(DecryptError::InvalidCodepoint)?);
+            }
+        } else {
+            let len = (value / 2) as usize + MIN_MATCH;
+            let distance = read_number(&mut digits)?.ok_or(DecryptError::InvalidToken)?;
+            let distance = distance as usize + 1;
+            if len > MAX_MATCH || distance > out.len() {
+                return Err(DecryptError::InvalidToken);
+            }
+            if len > limit - out.len() {
+                return Err(DecryptError::TokenTooLong);
+            }
+            let start = out.len() - distance;
+            for k in 0..len {
+                out.push(out[start + k]);
+            }
+        }
+    }
+    Ok(out.into_iter().collect())
+}
+
+/// Finds the longest earlier repeat of the text at `i`, as (length, distance).
+fn best_match(
+    chars: &[char],
+    i: usize,
+    sources: &HashMap<(char, char), Vec<usize>>,
+) -> Option<(usize, usize)> {
+    let key = (chars[i], *chars.get(i + 1)?);
+    let limit = (chars.len() - i).min(MAX_MATCH);
+    let mut best: Option<(usize, usize)> = None;
+    for &start in sources.get(&key)?.iter().rev().take(MAX_CANDIDATES) {
+        let len = (0..limit).take_while(|&k| chars[start + k] == chars[i + k]).count();
+        if best.is_none_or(|(best_len, _)| len > best_len) {
+            best = Some((len, i - start));
+        }
+    }
+    best
+}
+
+/// Writes pending literal characters as one item.
+fn flush_literals(out: &mut String, literals: &mut Vec<char>, table: &FrequencyTable) {
+    if literals.is_empty() {
+        return;
+    }
+    push_number(out, ((literals.len() - 1) * 2) as u32);
+    for &c in literals.iter() {
+        push_number(out, table.rank(c));
+    }
+    literals.clear();
+}
+
+/// Letters needed for a back-reference.
+fn match_cost(len: usize, distance: usize) -> usize {
+    number_len((((len - MIN_MATCH) * 2) + 1) as u32) + number_len((distance - 1) as u32)
+}
+
+/// Letters needed to write characters as literals, ignoring the item header.
+fn literal_cost(chars: &[char], table: &FrequencyTable) -> usize {
+    chars.iter().map(|&c| number_len(table.rank(c))).sum()
+}
+
+/// Number of letters [`push_number`] writes for a value.
+fn number_len(mut value: u32) -> usize {
+    let mut len = 1;
+    while value >= BASE {
+        value /= BASE;
+        len += 1;
+    }
+    len
+}
+
+/// Appends a number in the self-delimiting base-13 letter form.
+fn push_number(out: &mut String, value: u32) {
+    let len = number_len(value);
+    for i in (0..len as u32).rev() {
+        let digit = (value / BASE.pow(i)) % BASE;
+        let offset = if i == 0 { 0 } else { BASE };
+        out.push((b'a' + (digit + offset) as u8) as char);
+    }
+}
+
+/// Reads a number written by [`push_number`], or `None` at the end of the stream.
+fn read_number(digits: &mut impl Iterator<Item = u32>) -> Result<Option<u32>, DecryptError> {
+    let Some(mut digit) = digits.next() else {
+        return Ok(None);
+    };
+    let mut value: u32 = 0;
+    while digit >= BASE {
+        value = value
+            .checked_mul(BASE)
+            .and_then(|v| v.checked_add(digit - BASE))
+            .ok_or(DecryptError::InvalidToken)?;
+        digit = digits.next().ok_or(DecryptError::InvalidToken)?;
+    }
+    let value = value
+        .checked_mul(BASE)
+        .and_then(|v| v.checked_add(digit))
+        .ok_or(DecryptError::InvalidToken)?;
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numbers() {
+        for value in [0, 1, 12, 13, 168, 169, 2196, 2197, u32::MAX] {
+            let mut out = String::new();
+            push_number(&mut out, value);
+            assert_eq!(out.len(), number_len(value));
+            let mut digits = out.bytes().map(|b| u32::from(b - b'a'));
+            assert_eq!(read_number(&mut digits), Ok(Some(value)));
+            assert_eq!(read_number(&mut digits), Ok(None));
+        }
+        let mut truncated = "nn".bytes().map(|b| u32::from(b - b'a'));
+        assert_eq!(read_number(&mut truncated), Err(DecryptError::InvalidToken));
+        let mut overflow = "zzzzzzzzzm".bytes().map(|b| u32::from(b - b'a'));
+        assert_eq!(read_number(&mut overflow), Err(DecryptError::InvalidToken));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let table = FrequencyTable::bundled();
+        for text in [
+            "",
+            "a",
+            "哈哈哈哈哈哈哈哈哈哈",
+            "abababababab",
+            "the cat and the hat and the bat",
+            "你好你好，世界世界！😀😀😀",
+            &"x".repeat(10_000),
+        ] {
+            let stream = compress(text, &table);
+            assert!(stream.chars().all(|c| c.is_ascii_lowercase()));
+            assert_eq!(expand(&stream, &table, MAX_EXPANDED_LENGTH).unwrap(), text);
+        }
+        // A run costs a literal and one back-reference.
+        assert_eq!(compress(&"e".repeat(100), &table).len(), 1 + 1 + 3 + 1);
+    }
+
+    #[test]
+    fn test_expand_invalid() {
+        let table = FrequencyTable::bundled();
+        // Back-reference before the start of the text.
+        assert_eq!(expand("bb", &table, MAX_EXPANDED_LENGTH), Err(DecryptError::InvalidToken));
+        // Literal run missing its characters.
+        assert_eq!(expand("c", &table, MAX_EXPANDED_LENGTH), Err(DecryptError::InvalidToken));
+        // Over-long copy.
+        assert_eq!(expand("abrupaa", &table, MAX_EXPANDED_LENGTH), Err(DecryptError::InvalidToken));
+    }
+
+    #[test]
+    fn test_expand_limit() {
+        let table = FrequencyTable::bundled();
+        // One literal followed by copies of the longest length: each copy
+        // takes a few letters and expands to 4096 characters.
+        let literal = compress("x", &table);
+        let copy = &compress(&"x".repeat(1 + MAX_MATCH), &table)[literal.len()..];
+        let hostile = format!("{literal}{}", copy.repeat(300));
+        assert!(hostile.len() < 2000);
+        assert_eq!(expand(&hostile, &table, MAX_EXPANDED_LENGTH), Err(DecryptError::TokenTooLong));
+        let len = 1 + 300 * MAX_MATCH;
+        assert_eq!(expand(&hostile, &table, len).unwrap().len(), len);
+        assert_eq!(expand(&literal, &table, 1).unwrap(), "x");
+        assert_eq!(expand(&compress("xy", &table), &table, 1), Err(DecryptError::TokenTooLong));
+    }
+}
@@ -0,0 +1,142 @@
+use subtle::{ConditionallySelectable, ConstantTimeEq, ConstantTimeLess};
+
+/// Compares two tokens in time that depends only on their lengths.
+///
+/// Use it instead of `==` when a token is checked against a secret one, such
+/// as an expected authentication token, so the comparison does not reveal
+/// how long the common prefix is. Tokens of different lengths compare
+/// unequal right away.
+///
+/// # Examples
+///
+/// ```
+/// use uniaz::{ct_eq, UniAz};
+///
+/// let uni_az = UniAz::builder().key(b"secret").build();
+/// let expected = uni_az.encode_u64(42);
+/// assert!(ct_eq(&uni_az.encode_u64(42), &expected));
+/// assert!(!ct_eq("abc", &expected));
+/// ```
+pub fn ct_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+/// Reduces `x < 2^32` modulo a public `modulus` without hardware division on `x`.
+///
+/// Barrett reduction: the quotient estimate is off by at most one, which a
+/// final conditional subtraction corrects.
+pub(crate) fn reduce(x: u64, modulus: u64) -> u64 {
+    debug_assert!(x < 1 << 32 && modulus > 0);
+    let inverse = (1u64 << 32) / modulus;
+    let quotient = (x * inverse) >> 32;
+    subtract(x - quotient * modulus, modulus)
+}
+
+/// Reduces `x < 2 * modulus` modulo `modulus` without branching.
+pub(crate) fn subtract(x: u64, modulus: u64) -> u64 {
+    let below = x.ct_lt(&modulus);
+    x - u64::conditional_select(&modulus, &0, below)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DecryptError, UniAz};
+    use std::time::Instant;
+
+    #[test]
+    fn test_ct_eq() {
+        assert!(ct_eq("", ""));
+        assert!(ct_eq("abpx", "abpx"));
+        assert!(!ct_eq("abpx", "abpy"));
+        assert!(!ct_eq("abpx", "abp"));
+    }
+
+    #[test]
+    fn test_reduce() {
+        for modulus in 1..=256 {
+            for x in (0..256 * 257).chain([u32::MAX as u64]) {
+                assert_eq!(reduce(x, modulus), x % modulus, "{x} % {modulus}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_decrypt_ct_matches() {
+        let u = UniAz::builder().key(b"secret").build();
+        let mut tokens: Vec<String> = Vec::new();
+        for len in 1..=3 {
+            let mut token = vec![b'a'; len];
+            loop {
+                tokens.push(String::from_utf8(token.clone()).unwrap());
+                let Some(i) = token.iter().rposition(|&b| b != b'z') else { break };
+                token[i] += 1;
+                token[i + 1..].fill(b'a');
+            }
+        }
+        tokens.extend("你好世界😀\u{10FFFF}\u{E000}".chars().map(|c| u.encrypt(c)));
+        tokens.extend(["zzzzz".into(), "aaaaa".into(), "abcdef".into(), "AB".into(), "".into()]);
+        let lenient = UniAz::builder().key(b"secret").lenient(true).build();
+        for token in &tokens {
+            assert_eq!(u.decrypt_ct(token), u.decrypt(token), "{token:?}");
+            assert_eq!(lenient.decrypt_ct(token), lenient.decrypt(token), "{token:?}");
+        }
+    }
+
+    /// Welch's t statistic of two timing samples, after dropping the slowest
+    /// tenth of each, which is mostly scheduler and interrupt noise.
+    fn welch_t(mut a: Vec<f64>, mut b: Vec<f64>) -> f64 {
+        let stats = |samples: &mut Vec<f64>| {
+            samples.sort_unstable_by(f64::total_cmp);
+            samples.truncate(samples.len() * 9 / 10);
+            let n = samples.len() as f64;
+            let mean = samples.iter().sum::<f64>() / n;
+            let var = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+            (mean, var / n)
+        };
+        let (mean_a, se_a) = stats(&mut a);
+        let (mean_b, se_b) = stats(&mut b);
+        (mean_a - mean_b) / (se_a + se_b).sqrt()
+    }
+
+    /// Times `decode` on tokens of a fixed character against tokens of random
+    /// characters with tokens of the same length, in random order, in the
+    /// style of dudect, and returns the t statistic. Values beyond about 10
+    /// indicate that the timing depends on the plaintext.
+    fn timing_t(
+        decode: impl Fn(&UniAz, &str) -> Result<char, DecryptError>,
+        samples: usize,
+    ) -> f64 {
+        let u = UniAz::builder().key(b"timing").build();
+        let mut state = 0x2545_F491_4F6C_DD1Du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let mut times = [Vec::with_capacity(samples), Vec::with_capacity(samples)];
+        for _ in 0..2 * samples {
+            let class = (next() % 2) as usize;
+            // Characters with 4-letter tokens, like '你'.
+            let random = char::from_u32(17_576 + (next() % 30_000) as u32).unwrap();
+            let token = u.encrypt([random, '你'][class]);
+            let start = Instant::now();
+            let result = std::hint::black_box(decode(&u, std::hint::black_box(&token)));
+            times[class].push(start.elapsed().as_nanos() as f64);
+            assert!(result.is_ok());
+        }
+        let [a, b] = times;
+        welch_t(a, b)
+    }
+
+    #[test]
+    #[ignore = "timing-sensitive; run with `cargo test -- --ignored timing`"]
+    fn test_decrypt_ct_timing() {
+        let t = timing_t(UniAz::decrypt_ct, 200_000);
+        assert!(t.abs() < 10.0, "t = {t}");
+        // The harness does catch the early exits of the regular path.
+        let t = timing_t(UniAz::decrypt, 200_000);
+        assert!(t.abs() > 10.0, "t = {t}");
+    }
+}
@@ -0,0 +1,215 @@
+use crate::DecryptError;
+
+/// Letters every envelope header starts with.
+const MAGIC: &str = "uz";
+
+/// Length of an envelope header token in letters, without the round count
+/// of version 3 headers.
+///
+/// Character tokens are at most 5 letters long, so a header can never be
+/// mistaken for the first character of a headerless (v1) message.
+pub(crate) const HEADER_LEN: usize = 11;
+
+/// Format version written by this crate. Version 1 is the headerless format.
+pub(crate) const VERSION: u8 = 2;
+
+/// Format version of headers that record a non-default round count.
+pub(crate) const VERSION_ROUNDS: u8 = 3;
+
+/// Largest round count a header can record, in its single extra letter.
+pub(crate) const MAX_ROUNDS: u8 = 25;
+
+/// Alphabet id of the Latin alphabet `a-z`.
+pub(crate) const ALPHABET_LATIN: u8 = 0;
+
+/// A nonce token follows the header.
+pub(crate) const FLAG_NONCE: u16 = 1 << 0;
+/// Character tweaks use [`TweakMode::PositionV1`](crate::TweakMode::PositionV1).
+pub(crate) const FLAG_POSITION_V1: u16 = 1 << 1;
+/// A MAC tag token ends the message.
+pub(crate) const FLAG_MAC: u16 = 1 << 2;
+/// Character tokens are grouped by grapheme cluster.
+pub(crate) const FLAG_GRAPHEME: u16 = 1 << 3;
+/// The message keeps the line structure of the text.
+pub(crate) const FLAG_LINES: u16 = 1 << 4;
+/// Characters use [`TokenEncoding::Compact`](crate::TokenEncoding::Compact).
+pub(crate) const FLAG_COMPACT: u16 = 1 << 5;
+/// Text is compressed into one stream token per line.
+pub(crate) const FLAG_COMPRESSED: u16 = 1 << 6;
+/// Characters use [`TokenEncoding::Pronounceable`](crate::TokenEncoding::Pronounceable).
+pub(crate) const FLAG_PRONOUNCEABLE: u16 = 1 << 7;
+/// Characters use [`TokenEncoding::OrderPreserving`](crate::TokenEncoding::OrderPreserving).
+pub(crate) const FLAG_ORDER_PRESERVING: u16 = 1 << 8;
+/// Tokens are enciphered with FF1 instead of [`Cipher`](crate::Cipher).
+pub(crate) const FLAG_FF1: u16 = 1 << 9;
+/// All flags understood by this version.
+const KNOWN_FLAGS: u16 = FLAG_NONCE
+    | FLAG_POSITION_V1
+    | FLAG_MAC
+    | FLAG_GRAPHEME
+    | FLAG_LINES
+    | FLAG_COMPACT
+    | FLAG_COMPRESSED
+    | FLAG_PRONOUNCEABLE
+    | FLAG_ORDER_PRESERVING
+    | FLAG_FF1;
+
+/// Self-describing header of an enveloped message.
+///
+/// The header is a single token of plain letters:
+///
+/// | letters | field                              |
+/// |---------|------------------------------------|
+/// | 2       | magic `uz`                         |
+/// | 1       | format version                     |
+/// | 1       | alphabet id                        |
+/// | 3       | mode flags, base 26, big-endian    |
+/// | 4       | key id, base 26, big-endian        |
+/// | 0 or 1  | round count, version 3 only        |
+///
+/// Version 2 headers imply the default of 2 rounds, so output with default
+/// rounds keeps the version 2 layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Envelope {
+    pub(crate) version: u8,
+    pub(crate) alphabet_id: u8,
+    pub(crate) flags: u16,
+    pub(crate) key_id: u16,
+    pub(crate) rounds: u8,
+}
+
+impl Envelope {
+    /// Checks whether a token is an envelope header rather than a character token.
+    pub(crate) fn is_header(token: &str) -> bool {
+        (token.len() == HEADER_LEN || token.len() == HEADER_LEN + 1) && token.starts_with(MAGIC)
+    }
+
+    /// Renders the header token.
+    pub(crate) fn encode(&self) -> String {
+        let mut header = String::from(MAGIC);
+        push_letters(&mut header, u64::from(self.version), 1);
+        push_letters(&mut header, u64::from(self.alphabet_id), 1);
+        push_letters(&mut header, u64::from(self.flags), 3);
+        push_letters(&mut header, u64::from(self.key_id), 4);
+        if self.version == VERSION_ROUNDS {
+            push_letters(&mut header, u64::from(self.rounds), 1);
+        }
+        header
+    }
+
+    /// Parses and validates a header token.
+    ///
+    /// Returns [`DecryptError::UnsupportedFormat`] for versions, alphabets or
+    /// flags this crate does not understand.
+    pub(crate) fn parse(token: &str) -> Result<Self, DecryptError> {
+        if !Self::is_header(token) || !token.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(DecryptError::InvalidCipherText);
+        }
+        let field = |range: std::ops::Range<usize>| read_letters(&token[range]);
+        let version = field(2..3);
+        let alphabet_id = field(3..4);
+        let flags = field(4..7);
+        let key_id = u16::try_from(field(7..11)).map_err(|_| DecryptError::UnsupportedFormat)?;
+        let rounds = match (version, token.len()) {
+            (v, HEADER_LEN) if v == u64::from(VERSION) => 2,
+            (v, len) if v == u64::from(VERSION_ROUNDS) && len == HEADER_LEN + 1 => field(11..12),
+            _ => return Err(DecryptError::UnsupportedFormat),
+        };
+        if rounds == 0
+            || alphabet_id != u64::from(ALPHABET_LATIN)
+            || flags & !u64::from(KNOWN_FLAGS) != 0
+            || conflicting(flags as u16)
+        {
+            return Err(DecryptError::UnsupportedFormat);
+        }
+        Ok(Envelope {
+            version: version as u8,
+            alphabet_id: ALPHABET_LATIN,
+            flags: flags as u16,
+            key_id,
+            rounds: rounds as u8,
+        })
+    }
+
+    /// Cipher iterations per token of the message.
+    pub(crate) fn rounds(&self) -> usize {
+        usize::from(self.rounds)
+    }
+
+    /// Checks whether a flag is set.
+    pub(crate) fn has(&self, flag: u16) -> bool {
+        self.flags & flag != 0
+    }
+}
+
+/// Checks for flag combinations that no instance writes.
+fn conflicting(flags: u16) -> bool {
+    // FF1 needs tokens of at least 5 letters, which compact tokens and the
+    // last block of a stream do not have.
+    flags & FLAG_FF1 != 0 && flags & (FLAG_COMPRESSED | FLAG_COMPACT) != 0
+}
+
+/// Appends `value` as exactly `width` base-26 letters.
+fn push_letters(out: &mut String, value: u64, width: usize) {
+    for i in (0..width as u32).rev() {
+        let digit = (value / 26u64.pow(i)) % 26;
+        out.push((b'a' + digit as u8) as char);
+    }
+}
+
+/// Reads base-26 letters written by [`push_letters`].
+fn read_letters(letters: &str) -> u64 {
+    letters.bytes().fold(0, |acc, b| acc * 26 + u64::from(b - b'a'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let envelope = Envelope {
+            version: VERSION,
+            alphabet_id: ALPHABET_LATIN,
+            flags: FLAG_NONCE | FLAG_MAC,
+            key_id: u16::MAX,
+            rounds: 2,
+        };
+        let header = envelope.encode();
+        assert_eq!(header.len(), HEADER_LEN);
+        assert!(Envelope::is_header(&header));
+        assert_eq!(Envelope::parse(&header), Ok(envelope));
+        assert!(envelope.has(FLAG_MAC));
+        assert!(!envelope.has(FLAG_POSITION_V1));
+    }
+
+    #[test]
+    fn test_frozen_layout() {
+        let envelope = Envelope {
+            version: VERSION,
+            alphabet_id: ALPHABET_LATIN,
+            flags: FLAG_POSITION_V1,
+            key_id: 27,
+            rounds: 2,
+        };
+        assert_eq!(envelope.encode(), "uzcaaacaabb");
+        let envelope = Envelope { version: VERSION_ROUNDS, rounds: 5, ..envelope };
+        assert_eq!(envelope.encode(), "uzdaaacaabbf");
+        assert_eq!(Envelope::parse("uzdaaacaabbf"), Ok(envelope));
+    }
+
+    #[test]
+    fn test_rejects_unknown() {
+        assert_eq!(Envelope::parse("uzdaaaaaaaa"), Err(DecryptError::UnsupportedFormat));
+        assert_eq!(Envelope::parse("uzcaaaaaaaac"), Err(DecryptError::UnsupportedFormat));
+        assert_eq!(Envelope::parse("uzdaaaaaaaaa"), Err(DecryptError::UnsupportedFormat));
+        assert_eq!(Envelope::parse("uzeaaaaaaaac"), Err(DecryptError::UnsupportedFormat));
+        assert_eq!(Envelope::parse("uzcbaaaaaaa"), Err(DecryptError::UnsupportedFormat));
+        assert_eq!(Envelope::parse("uzcabnkaaaa"), Err(DecryptError::UnsupportedFormat));
+        // FF1 with compression or compact tokens.
+        assert_eq!(Envelope::parse("uzcaaweaaaa"), Err(DecryptError::UnsupportedFormat));
+        assert_eq!(Envelope::parse("uzcaauyaaaa"), Err(DecryptError::UnsupportedFormat));
+        assert_eq!(Envelope::parse("uzcaaaazzzz"), Err(DecryptError::UnsupportedFormat));
+        assert_eq!(Envelope::parse("uzcaaaaaaaA"), Err(DecryptError::InvalidCipherText));
+    }
+}
@@ -0,0 +1,469 @@
+use crate::backend::TokenCipher;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::{Aes128, Aes192, Aes256, Block};
+use std::collections::HashMap;
+use zeroize::{Zeroize, Zeroizing};
+
+/// Number of Feistel rounds fixed by the standard.
+const ROUNDS: u8 = 10;
+
+/// Smallest domain the standard allows: `radix^min_len` must reach it.
+const MIN_DOMAIN: u128 = 1_000_000;
+
+/// Largest half-string value kept in a `u128`, leaving room to shift in a byte.
+const MAX_HALF: u128 = 1 << 120;
+
+/// The FF1 format-preserving encryption mode of AES, as specified in NIST
+/// SP 800-38G Rev. 1.
+///
+/// Unlike [`Cipher`](crate::Cipher), whose round function has no published
+/// analysis, FF1 is a standardized mode whose security reduces to AES.
+/// Strings over the alphabet are enciphered as numerals of the alphabet's
+/// radix; characters outside the alphabet are copied through unchanged.
+/// The standard requires at least a million possible inputs, so inputs are
+/// at least [`min_len`](Self::min_len) characters long (5 for `a-z`).
+///
+/// Requires the `ff1` feature.
+///
+/// # Examples
+///
+/// ```
+/// use uniaz::Ff1;
+///
+/// let key = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6,
+///            0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c];
+/// let ff1 = Ff1::new(&key, "0123456789").unwrap();
+/// assert_eq!(ff1.encrypt("0123456789", b""), "2433477484");
+/// assert_eq!(ff1.decrypt("2433477484", b""), "0123456789");
+/// ```
+pub struct Ff1 {
+    aes: Aes,
+    alphabet: Vec<char>,
+    /// Value of each alphabet character.
+    values: HashMap<char, u32>,
+    min_len: usize,
+    max_len: usize,
+}
+
+/// AES with any of the standard key sizes.
+enum Aes {
+    Aes128(Aes128),
+    Aes192(Aes192),
+    Aes256(Aes256),
+}
+
+impl Aes {
+    fn encrypt_block(&self, block: &mut [u8; 16]) {
+        let block = Block::from_mut_slice(block);
+        match self {
+            Aes::Aes128(aes) => aes.encrypt_block(block),
+            Aes::Aes192(aes) => aes.encrypt_block(block),
+            Aes::Aes256(aes) => aes.encrypt_block(block),
+        }
+    }
+}
+
+impl Ff1 {
+    /// Creates an FF1 cipher from an AES key of 16, 24 or 32 bytes and an
+    /// alphabet of 2 to 65536 distinct characters.
+    pub fn new(key: &[u8], alphabet: &str) -> Result<Self, Ff1Error> {
+        let aes = match key.len() {
+            16 => Aes::Aes128(Aes128::new_from_slice(key).expect("key length checked")),
+            24 => Aes::Aes192(Aes192::new_from_slice(key).expect("key length checked")),
+            32 => Aes::Aes256(Aes256::new_from_slice(key).expect("key length checked")),
+            len => return Err(Ff1Error::KeyLength(len)),
+        };
+        let alphabet: Vec<char> = alphabet.chars().collect();
+        if !(2..=1 << 16).contains(&alphabet.len()) {
+            return Err(Ff1Error::Radix(alphabet.len()));
+        }
+        let mut values = HashMap::with_capacity(alphabet.len());
+        for (i, &c) in alphabet.iter().enumerate() {
+            if values.insert(c, i as u32).is_some() {
+                return Err(Ff1Error::Duplicate(c));
+            }
+        }
+
+        let radix = alphabet.len() as u128;
+        let mut min_len = 2;
+        while radix.pow(min_len) < MIN_DOMAIN {
+            min_len += 1;
+        }
+        let mut max_half = 1;
+        while radix.checked_pow(max_half + 1).is_some_and(|p| p <= MAX_HALF) {
+            max_half += 1;
+        }
+        Ok(Ff1 {
+            aes,
+            alphabet,
+            values,
+            min_len: min_len as usize,
+            max_len: 2 * max_half as usize,
+        })
+    }
+
+    /// The number of characters in the alphabet.
+    pub fn radix(&self) -> usize {
+        self.alphabet.len()
+    }
+
+    /// Fewest alphabet characters an input may have: the least length with
+    /// at least a million possible inputs.
+    pub fn min_len(&self) -> usize {
+        self.min_len
+    }
+
+    /// Most alphabet characters an input may have, so that half of it fits
+    /// in 120 bits: 50 for `a-z`.
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Encrypts a string with a public tweak of any length.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of alphabet characters in `input` is outside
+    /// [`min_len`](Self::min_len)..=[`max_len`](Self::max_len).
+    pub fn encrypt(&self, input: &str, tweak: &[u8]) -> String {
+        self.map_numerals(input, |numerals| self.feistel(numerals, tweak, false))
+    }
+
+    /// Decrypts a string encrypted with the same tweak.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as [`encrypt`](Self::encrypt).
+    pub fn decrypt(&self, input: &str, tweak: &[u8]) -> String {
+        self.map_numerals(input, |numerals| self.feistel(numerals, tweak, true))
+    }
+
+    /// Applies `f` to the numerals of the alphabet characters of `input`,
+    /// leaving other characters in place.
+    fn map_numerals(&self, input: &str, f: impl FnOnce(&[u32]) -> Vec<u32>) -> String {
+        let numerals: Vec<u32> =
+            input.chars().filter_map(|c| self.values.get(&c)).copied().collect();
+        if !(self.min_len..=self.max_len).contains(&numerals.len()) {
+            panic!(
+                "FF1 input must have {} to {} alphabet characters, got {}",
+                self.min_len,
+                self.max_len,
+                numerals.len()
+            );
+        }
+        let mut mapped = f(&numerals).into_iter();
+        input
+            .chars()
+            .map(|c| {
+                if self.values.contains_key(&c) {
+                    self.alphabet[mapped.next().expect("same length") as usize]
+                } else {
+                    c
+                }
+            })
+            .collect()
+    }
+
+    /// The Feistel network of Algorithms 7 and 8 of SP 800-38G.
+    fn feistel(&self, x: &[u32], tweak: &[u8], decrypt: bool) -> Vec<u32> {
+        let radix = self.radix() as u128;
+        let n = x.len();
+        let u = n / 2;
+        let v = n - u;
+        let (mut a, mut b) = (num(&x[..u], radix), num(&x[u..], radix));
+
+        // Bytes of the right half, and of the pseudo-random output.
+        let bits = (u128::BITS - (radix.pow(v as u32) - 1).leading_zeros()) as usize;
+        let b_len = bits.div_ceil(8);
+        let d = 4 * b_len.div_ceil(4) + 4;
+
+        let mut p = [1, 2, 1, 0, 0, 0, 10, u as u8, 0, 0, 0, 0, 0, 0, 0, 0];
+        p[3..6].copy_from_slice(&(radix as u32).to_be_bytes()[1..]);
+        p[8..12].copy_from_slice(&(n as u32).to_be_bytes());
+        p[12..16].copy_from_slice(&(tweak.len() as u32).to_be_bytes());
+        let mut prefix = p;
+        self.aes.encrypt_block(&mut prefix);
+        let padding = (16 - (tweak.len() + b_len + 1) % 16) % 16;
+
+        for step in 0..ROUNDS {
+            let i = if decrypt { ROUNDS - 1 - step } else { step };
+            let m = if i % 2 == 0 { u } else { v };
+            let modulus = radix.pow(m as u32);
+
+            // Q = T || 0^padding || [i]^1 || [NUM(B)]^b, fed to the CBC-MAC after P.
+            let mut q = Vec::with_capacity(tweak.len() + padding + 1 + b_len);
+            q.extend_from_slice(tweak);
+            q.resize(tweak.len() + padding, 0);
+            q.push(i);
+            let right = if decrypt { a } else { b };
+            q.extend_from_slice(&right.to_be_bytes()[16 - b_len..]);
+            let mut r = prefix;
+            for chunk in q.chunks(16) {
+                r.iter_mut().zip(chunk).for_each(|(r, q)| *r ^= q);
+                self.aes.encrypt_block(&mut r);
+            }
+
+            // S = R || CIPH(R ^ [1]^16) || CIPH(R ^ [2]^16) || ..., cut to d bytes.
+            let mut y = 0u128;
+            for j in 0..d.div_ceil(16) {
+                let mut block = r;
+                if j > 0 {
+                    let counter = (j as u128).to_be_bytes();
+                    block.iter_mut().zip(counter).for_each(|(s, c)| *s ^= c);
+                    self.aes.encrypt_block(&mut block);
+                }
+                for &byte in &block[..(d - 16 * j).min(16)] {
+                    y = (y * 256 + u128::from(byte)) % modulus;
+                }
+            }
+
+            if decrypt {
+                let c = (b + modulus - y) % modulus;
+                b = a;
+                a = c;
+            } else {
+                let c = (a + y) % modulus;
+                a = b;
+                b = c;
+            }
+        }
+        let mut out = str_m(a, radix, u);
+        out.extend(str_m(b, radix, v));
+        out
+    }
+}
+
+impl Ff1 {
+    /// Derives a `len`-byte key from the AES key with the KDF in counter mode
+    /// of NIST SP 800-108r1, using AES-CMAC as the PRF and an empty context.
+    ///
+    /// Other keys of an FF1 instance come from here, so the user key only
+    /// ever keys AES.
+    pub(crate) fn derive_key(&self, label: &[u8], len: usize) -> Zeroizing<Vec<u8>> {
+        let bits = u32::try_from(len * 8).expect("derived keys are short");
+        let mut derived = Zeroizing::new(Vec::with_capacity(len + 16));
+        let mut input = Vec::with_capacity(label.len() + 9);
+        for counter in 1..=len.div_ceil(16) as u32 {
+            // [i]_32 || Label || 0x00 || [L]_32
+            input.clear();
+            input.extend_from_slice(&counter.to_be_bytes());
+            input.extend_from_slice(label);
+            input.push(0);
+            input.extend_from_slice(&bits.to_be_bytes());
+            derived.extend_from_slice(&self.cmac(&input));
+        }
+        derived.truncate(len);
+        derived
+    }
+
+    /// AES-CMAC of a message, as specified in NIST SP 800-38B.
+    fn cmac(&self, message: &[u8]) -> [u8; 16] {
+        let mut k1 = [0u8; 16];
+        self.aes.encrypt_block(&mut k1);
+        k1 = double(k1);
+        let mut k2 = double(k1);
+
+        let blocks = message.len().div_ceil(16).max(1);
+        let mut state = [0u8; 16];
+        for i in 0..blocks {
+            let chunk = &message[16 * i..(16 * i + 16).min(message.len())];
+            let mut block = [0u8; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            if i == blocks - 1 {
+                // A complete last block is masked with K1, a padded one with K2.
+                let subkey = if chunk.len() == 16 {
+                    &k1
+                } else {
+                    block[chunk.len()] = 0x80;
+                    &k2
+                };
+                block.iter_mut().zip(subkey).for_each(|(b, k)| *b ^= k);
+            }
+            state.iter_mut().zip(block).for_each(|(s, b)| *s ^= b);
+            self.aes.encrypt_block(&mut state);
+        }
+        k1.zeroize();
+        k2.zeroize();
+        state
+    }
+}
+
+/// Doubling in GF(2^128), which derives the CMAC subkeys.
+fn double(block: [u8; 16]) -> [u8; 16] {
+    let value = u128::from_be_bytes(block);
+    let carry = if value >> 127 == 1 { 0x87 } else { 0 };
+    ((value << 1) ^ carry).to_be_bytes()
+}
+
+/// `NUM_radix(X)`: the numerals as a big-endian number.
+fn num(numerals: &[u32], radix: u128) -> u128 {
+    numerals.iter().fold(0, |acc, &x| acc * radix + u128::from(x))
+}
+
+/// `STR_m_radix(x)`: `x` as exactly `m` big-endian numerals.
+fn str_m(mut x: u128, radix: u128, m: usize) -> Vec<u32> {
+    let mut out = vec![0; m];
+    for numeral in out.iter_mut().rev() {
+        *numeral = (x % radix) as u32;
+        x /= radix;
+    }
+    out
+}
+
+impl TokenCipher for Ff1 {
+    /// Encrypts with the tweak as 8 big-endian bytes.
+    fn encipher(&self, input: &str, _rounds: usize, tweak: u64) -> String {
+        if input.is_empty() {
+            return String::new();
+        }
+        self.encrypt(input, &tweak.to_be_bytes())
+    }
+
+    fn decipher(&self, input: &str, _rounds: usize, tweak: u64) -> String {
+        if input.is_empty() {
+            return String::new();
+        }
+        self.decrypt(input, &tweak.to_be_bytes())
+    }
+
+    fn min_len(&self) -> usize {
+        self.min_len
+    }
+
+    fn max_len(&self) -> usize {
+        self.max_len
+    }
+}
+
+impl std::fmt::Debug for Ff1 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The AES key schedule is key material.
+        f.debug_struct("Ff1").field("radix", &self.radix()).finish_non_exhaustive()
+    }
+}
+
+/// Error returned by [`Ff1::new`] for an unusable key or alphabet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ff1Error {
+    /// The key is not 16, 24 or 32 bytes long.
+    KeyLength(usize),
+    /// The alphabet does not have 2 to 65536 characters.
+    Radix(usize),
+    /// The character occurs more than once.
+    Duplicate(char),
+}
+
+impl std::fmt::Display for Ff1Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ff1Error::KeyLength(len) => write!(f, "FF1 keys must be 16, 24 or 32 bytes, got {len}"),
+            Ff1Error::Radix(radix) => {
+                write!(f, "FF1 alphabets must have 2 to 65536 characters, got {radix}")
+            }
+            Ff1Error::Duplicate(c) => write!(f, "Character set contains {c:?} more than once"),
+        }
+    }
+}
+
+impl std::error::Error for Ff1Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_128: &str = "2B7E151628AED2A6ABF7158809CF4F3C";
+    const KEY_192: &str = "2B7E151628AED2A6ABF7158809CF4F3CEF4359D8D580AA4F";
+    const KEY_256: &str = "2B7E151628AED2A6ABF7158809CF4F3CEF4359D8D580AA4F7F036D6F04FC6A94";
+    const DECIMAL: &str = "0123456789";
+    const BASE36: &str = "0123456789abcdefghijklmnopqrstuvwxyz";
+    const TWEAK: &str = "39383736353433323130";
+    const TWEAK36: &str = "3737373770717273373737";
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    /// The FF1 samples published with SP 800-38G.
+    #[test]
+    fn test_nist_vectors() {
+        let vectors = [
+            (KEY_128, DECIMAL, "0123456789", "", "2433477484"),
+            (KEY_128, DECIMAL, "0123456789", TWEAK, "6124200773"),
+            (KEY_128, BASE36, "0123456789abcdefghi", TWEAK36, "a9tv40mll9kdu509eum"),
+            (KEY_192, DECIMAL, "0123456789", "", "2830668132"),
+            (KEY_192, DECIMAL, "0123456789", TWEAK, "2496655549"),
+            (KEY_192, BASE36, "0123456789abcdefghi", TWEAK36, "xbj3kv35jrawxv32ysr"),
+            (KEY_256, DECIMAL, "0123456789", "", "6657667009"),
+            (KEY_256, DECIMAL, "0123456789", TWEAK, "1001623463"),
+            (KEY_256, BASE36, "0123456789abcdefghi", TWEAK36, "xs8a0azh2avyalyzuwd"),
+        ];
+        for (key, alphabet, plain, tweak, cipher) in vectors {
+            let ff1 = Ff1::new(&hex(key), alphabet).unwrap();
+            assert_eq!(ff1.encrypt(plain, &hex(tweak)), cipher, "{key} {plain} {tweak}");
+            assert_eq!(ff1.decrypt(cipher, &hex(tweak)), plain, "{key} {cipher} {tweak}");
+        }
+    }
+
+    /// The AES-128 examples of RFC 4493, which match SP 800-38B.
+    #[test]
+    fn test_cmac() {
+        let ff1 = Ff1::new(&hex(KEY_128), DECIMAL).unwrap();
+        let message = hex(concat!(
+            "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51",
+            "30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710",
+        ));
+        let vectors = [
+            (0, "bb1d6929e95937287fa37d129b756746"),
+            (16, "070a16b46b4d4144f79bdd9dd04a287c"),
+            (40, "dfa66747de9ae63030ca32611497c827"),
+            (64, "51f0bebf7e3b9d92fc49741779363cfe"),
+        ];
+        for (len, tag) in vectors {
+            assert_eq!(ff1.cmac(&message[..len]).to_vec(), hex(tag), "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn test_derive_key() {
+        let ff1 = Ff1::new(&hex(KEY_128), DECIMAL).unwrap();
+        let key = ff1.derive_key(b"label", 32);
+        assert_eq!(key.len(), 32);
+        assert_eq!(key, ff1.derive_key(b"label", 32));
+        // The output length is part of the KDF input.
+        assert_ne!(key[..20], ff1.derive_key(b"label", 20)[..]);
+        assert_ne!(key, ff1.derive_key(b"other", 32));
+        assert_ne!(key, Ff1::new(&hex(KEY_192), DECIMAL).unwrap().derive_key(b"label", 32));
+    }
+
+    #[test]
+    fn test_lengths() {
+        let ff1 = Ff1::new(&hex(KEY_128), "abcdefghijklmnopqrstuvwxyz").unwrap();
+        assert_eq!((ff1.min_len(), ff1.max_len()), (5, 50));
+        for len in [5, 6, 13, 49, 50] {
+            let plain: String = (0..len).map(|i| (b'a' + (i * 7 % 26) as u8) as char).collect();
+            let cipher = ff1.encrypt(&plain, b"tweak");
+            assert_eq!(cipher.len(), len);
+            assert_ne!(cipher, plain);
+            assert_eq!(ff1.decrypt(&cipher, b"tweak"), plain);
+        }
+        // Characters outside the alphabet keep their place.
+        let cipher = ff1.encrypt("abc-de fg", b"");
+        assert_eq!((&cipher[3..4], &cipher[6..7]), ("-", " "));
+        assert_eq!(ff1.decrypt(&cipher, b""), "abc-de fg");
+        assert_eq!(Ff1::new(&hex(KEY_128), "01").unwrap().min_len(), 20);
+    }
+
+    #[test]
+    #[should_panic(expected = "FF1 input must have 5 to 50 alphabet characters, got 4")]
+    fn test_too_short() {
+        Ff1::new(&hex(KEY_128), "abcdefghijklmnopqrstuvwxyz").unwrap().encrypt("abcd", b"");
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(Ff1::new(&[0; 15], DECIMAL).unwrap_err(), Ff1Error::KeyLength(15));
+        assert_eq!(Ff1::new(&[0; 16], "a").unwrap_err(), Ff1Error::Radix(1));
+        assert_eq!(Ff1::new(&[0; 16], "abca").unwrap_err(), Ff1Error::Duplicate('a'));
+        assert_eq!(format!("{:?}", Ff1::new(&[0; 32], DECIMAL).unwrap()), "Ff1 { radix: 10, .. }");
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{DecryptError, UniAz, U64_WIDTH};
+
+/// How many extra padding letters [`UniAz::encode_u64`] tries before giving up
+/// on avoiding the blocklist.
+pub(crate) const BLOCKLIST_MAX_ATTEMPTS: usize = 16;
+
+impl UniAz {
+    /// Encodes an integer ID as an obfuscated letters-only token.
+    ///
+    /// The ID is written in the internal alphabet base, padded to the
+    /// configured [minimum length](crate::UniAzBuilder::min_id_length), and
+    /// encrypted with the configured number of
+    /// [rounds](crate::UniAzBuilder::rounds), so sequential IDs produce
+    /// unrelated-looking tokens.
+    ///
+    /// If the token contains a word from the configured
+    /// [blocklist](crate::UniAzBuilder::blocklist), the ID is padded by one more
+    /// letter and encrypted again, which yields a completely different token.
+    /// A pathological blocklist may reject every candidate; after
+    /// `16` extra letters the last candidate is returned so that encoding
+    /// stays infallible.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use uniaz::UniAz;
+    ///
+    /// let uni_az = UniAz::new();
+    /// let token = uni_az.encode_u64(1234);
+    /// assert_eq!(uni_az.decode_u64(&token).unwrap(), 1234);
+    /// ```
+    pub fn encode_u64(&self, id: u64) -> String {
+        let mut token = self.encrypt_fixed(id, self.min_id_length, self.scheme());
+        for _ in 0..BLOCKLIST_MAX_ATTEMPTS {
+            if !self.is_blocked(&token) {
+                break;
+            }
+            token = self.encrypt_fixed(id, token.len() + 1, self.scheme());
+        }
+        token
+    }
+
+    /// Decodes a token produced by [`encode_u64`](Self::encode_u64).
+    ///
+    /// Only the exact token that `encode_u64` produces for an ID is accepted:
+    /// tokens that decode to a number but differ from its canonical encoding
+    /// (too short, over-padded, or blocklisted) are rejected, so every ID has
+    /// exactly one valid token.
+    pub fn decode_u64(&self, token: &str) -> Result<u64, DecryptError> {
+        if token.is_empty() || !token.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(DecryptError::InvalidCipherText);
+        }
+        if token.len() > self.min_id_length.max(U64_WIDTH) + BLOCKLIST_MAX_ATTEMPTS {
+            return Err(DecryptError::InvalidToken);
+        }
+        let id = self.decrypt_fixed(token, self.scheme())?;
+        if self.encode_u64(id) != token {
+            return Err(DecryptError::InvalidToken);
+        }
+        Ok(id)
+    }
+
+    /// Checks whether the token contains any blocklisted word.
+    fn is_blocked(&self, token: &str) -> bool {
+        self.blocklist.iter().any(|word| token.contains(word.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::UniAz;
+
+    #[test]
+    fn test_roundtrip() {
+        let u = UniAz::new();
+        for id in (0..1000).chain([u64::from(u32::MAX), u64::MAX - 1, u64::MAX]) {
+            let token = u.encode_u64(id);
+            assert_eq!(u.decode_u64(&token).unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn test_sequential_ids_differ() {
+        let u = UniAz::builder().min_id_length(6).build();
+        let a = u.encode_u64(1000);
+        let b = u.encode_u64(1001);
+        assert_eq!(a.len(), 6);
+        assert_eq!(b.len(), 6);
+        assert!(a.chars().zip(b.chars()).filter(|(x, y)| x != y).count() > 1);
+    }
+
+    #[test]
+    fn test_min_length() {
+        let u = UniAz::builder().min_id_length(10).build();
+        for id in [0, 1, 25, 26, 1 << 40] {
+            let token = u.encode_u64(id);
+            assert_eq!(token.len(), 10);
+            assert_eq!(u.decode_u64(&token).unwrap(), id);
+        }
+        // Longer IDs are never truncated.
+        assert_eq!(u.encode_u64(u64::MAX).len(), 14);
+    }
+
+    #[test]
+    fn test_blocklist() {
+        let plain = UniAz::builder().min_id_length(8).build();
+        let blocked = plain.encode_u64(7)[2..5].to_uppercase();
+        let u = UniAz::builder()
+            .min_id_length(8)
+            .blocklist([blocked.as_str(), "", "x-y"])
+            .build();
+        let token = u.encode_u64(7);
+        assert!(!token.contains(&blocked.to_lowercase()));
+        assert_eq!(u.decode_u64(&token).unwrap(), 7);
+        // The blocklisted token is no longer accepted.
+        assert!(u.decode_u64(&plain.encode_u64(7)).is_err());
+    }
+
+    #[test]
+    fn test_decode_non_canonical() {
+        let u = UniAz::builder().min_id_length(8).build();
+        let token = u.encode_u64(42);
+        assert!(u.decode_u64(&token[1..]).is_err());
+        assert!(u.decode_u64(&UniAz::builder().min_id_length(9).build().encode_u64(42)).is_err());
+        assert!(u.decode_u64("").is_err());
+        assert!(u.decode_u64("ABC").is_err());
+        assert!(u.decode_u64(&"z".repeat(64)).is_err());
+    }
+}
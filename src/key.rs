@@ -0,0 +1,152 @@
+use siphasher::sip::SipHasher24;
+use std::hash::Hasher;
+use zeroize::Zeroize;
+
+/// Fixed SipHash keys separating the values derived from one user key.
+const MAC_DOMAIN: [u64; 2] = [0x7569_6e69_617a_6d61, 0x6320_6b65_7920_7631];
+const ALPHABET_DOMAIN: [u64; 2] = [0x7569_6e69_617a_616c, 0x7068_6162_6574_7631];
+
+/// User key held by a builder until [`build`](crate::UniAzBuilder::build).
+///
+/// The bytes are wiped on drop and never shown by `Debug`.
+#[derive(Clone)]
+pub(crate) struct SecretKey(Vec<u8>);
+
+impl SecretKey {
+    pub(crate) fn new(key: &[u8]) -> Self {
+        SecretKey(key.to_vec())
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Zeroize for SecretKey {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl std::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretKey(..)")
+    }
+}
+
+/// Keyed SipHash-2-4 used to authenticate whole messages.
+pub(crate) struct MacKey {
+    k0: u64,
+    k1: u64,
+}
+
+impl MacKey {
+    /// Derives the MAC key from an arbitrary-length user key.
+    pub(crate) fn derive(key: &[u8]) -> Self {
+        MacKey {
+            k0: prf(MAC_DOMAIN, key, 0),
+            k1: prf(MAC_DOMAIN, key, 1),
+        }
+    }
+
+    /// Computes the tag of a token sequence.
+    ///
+    /// Every token is followed by a separator byte, so moving letters between
+    /// adjacent tokens changes the tag.
+    pub(crate) fn tag<'a>(&self, tokens: impl IntoIterator<Item = &'a str>) -> u64 {
+        let mut hasher = SipHasher24::new_with_keys(self.k0, self.k1);
+        for token in tokens {
+            hasher.write(token.as_bytes());
+            hasher.write_u8(b' ');
+        }
+        hasher.finish()
+    }
+}
+
+impl Zeroize for MacKey {
+    fn zeroize(&mut self) {
+        self.k0.zeroize();
+        self.k1.zeroize();
+    }
+}
+
+impl Drop for MacKey {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+/// Returns `alphabet` shuffled by a permutation derived from `key`.
+///
+/// The shuffle is a Fisher-Yates pass driven by SipHash in counter mode, so the
+/// same key always yields the same alphabet order. The order is derived from
+/// the key, so callers should wipe the result once it is no longer needed.
+pub(crate) fn keyed_alphabet(alphabet: &str, key: &[u8]) -> String {
+    let mut chars: Vec<char> = alphabet.chars().collect();
+    for i in (1..chars.len()).rev() {
+        let j = prf(ALPHABET_DOMAIN, key, i as u64) % (i as u64 + 1);
+        chars.swap(i, j as usize);
+    }
+    let keyed = chars.iter().collect();
+    chars.zeroize();
+    keyed
+}
+
+/// SipHash-2-4 of `counter || key` under a fixed domain key.
+fn prf(domain: [u64; 2], key: &[u8], counter: u64) -> u64 {
+    let mut hasher = SipHasher24::new_with_keys(domain[0], domain[1]);
+    hasher.write_u64(counter);
+    hasher.write(key);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyed_alphabet() {
+        let alphabet = "abcdefghijklmnopqrstuvwxyz";
+        let keyed = keyed_alphabet(alphabet, b"secret");
+        assert_eq!(keyed, keyed_alphabet(alphabet, b"secret"));
+        assert_ne!(keyed, keyed_alphabet(alphabet, b"secret2"));
+        assert_ne!(keyed, alphabet);
+
+        let mut sorted: Vec<char> = keyed.chars().collect();
+        sorted.sort_unstable();
+        assert_eq!(sorted.into_iter().collect::<String>(), alphabet);
+    }
+
+    #[test]
+    fn test_tag() {
+        let key = MacKey::derive(b"secret");
+        let tag = key.tag(["ab", "cd"]);
+        assert_eq!(tag, key.tag(["ab", "cd"]));
+        assert_ne!(tag, key.tag(["abc", "d"]));
+        assert_ne!(tag, key.tag(["cd", "ab"]));
+        assert_ne!(tag, MacKey::derive(b"other").tag(["ab", "cd"]));
+    }
+
+    #[test]
+    fn test_zeroize() {
+        let mut key = MacKey::derive(b"secret");
+        assert_ne!((key.k0, key.k1), (0, 0));
+        key.zeroize();
+        assert_eq!((key.k0, key.k1), (0, 0));
+
+        let mut secret = SecretKey::new(b"hunter2");
+        assert_eq!(format!("{secret:?}"), "SecretKey(..)");
+        let (ptr, capacity) = (secret.0.as_ptr(), secret.0.capacity());
+        secret.zeroize();
+        // The key keeps its allocation, and zeroizing writes every byte of it.
+        assert_eq!(secret.0.as_ptr(), ptr);
+        let bytes = unsafe { std::slice::from_raw_parts(ptr, capacity) };
+        assert!(bytes.iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,175 @@
+use crate::envelope::Envelope;
+use crate::{DecryptError, UniAz};
+
+/// Keyed [`UniAz`] instances under rotation, looked up by key id.
+///
+/// Strings are encrypted with the current key, whose id is recorded in the
+/// envelope header, and decrypted with whichever key the header names, so
+/// text written before a rotation stays readable. Text without a header
+/// counts as key id `0`. Only [`encrypt_str`](Self::encrypt_str) output
+/// carries a key id; tokens of [`UniAz::encrypt`],
+/// [`UniAz::encrypt_bytes`] and [`UniAz::encode_u64`] must be decrypted
+/// with the right instance from [`get`](Self::get).
+///
+/// # Examples
+///
+/// ```
+/// use uniaz::{KeyRing, UniAz};
+///
+/// let old = UniAz::builder().key(b"old secret").key_id(1).build();
+/// let token = old.encrypt_str("你好");
+///
+/// let mut ring = KeyRing::new(old);
+/// ring.rotate(UniAz::builder().key(b"new secret").key_id(2).build());
+/// assert_eq!(ring.decrypt_str(&token).unwrap(), "你好");
+///
+/// let migrated = ring.reencrypt(&token).unwrap();
+/// assert_eq!(ring.current().decrypt_str(&migrated).unwrap(), "你好");
+/// ```
+#[derive(Debug)]
+pub struct KeyRing {
+    /// All instances, each with a distinct key id.
+    keys: Vec<UniAz>,
+    /// Index of the instance used for encryption.
+    current: usize,
+}
+
+impl KeyRing {
+    /// Creates a key ring whose current key is `current`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `current` does not write envelope headers, which it does
+    /// whenever a key is set, as its output would carry no key id.
+    pub fn new(current: UniAz) -> Self {
+        assert_envelope(&current);
+        KeyRing {
+            keys: vec![current],
+            current: 0,
+        }
+    }
+
+    /// Adds a key that is only used for decryption.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the ring already holds a key with the same key id.
+    pub fn add(&mut self, uni_az: UniAz) {
+        self.insert(uni_az);
+    }
+
+    /// Makes `uni_az` the current key; the previous one is kept for decryption.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `uni_az` does not write envelope headers, or if the ring
+    /// already holds a key with the same key id.
+    pub fn rotate(&mut self, uni_az: UniAz) {
+        assert_envelope(&uni_az);
+        self.current = self.insert(uni_az);
+    }
+
+    /// Returns the current key.
+    pub fn current(&self) -> &UniAz {
+        &self.keys[self.current]
+    }
+
+    /// Returns the key with the given key id.
+    pub fn get(&self, key_id: u16) -> Option<&UniAz> {
+        self.keys.iter().find(|uni_az| uni_az.key_id == key_id)
+    }
+
+    /// Encrypts a string with the current key, see [`UniAz::encrypt_str`].
+    pub fn encrypt_str(&self, text: &str) -> String {
+        self.current().encrypt_str(text)
+    }
+
+    /// Decrypts a string with the key named by its header, see
+    /// [`UniAz::decrypt_str`].
+    ///
+    /// Returns [`DecryptError::UnknownKey`] if the ring holds no such key.
+    pub fn decrypt_str(&self, text: &str) -> Result<String, DecryptError> {
+        let key_id = match text.split_whitespace().next() {
+            Some(header) if Envelope::is_header(header) => Envelope::parse(header)?.key_id,
+            _ => 0,
+        };
+        self.get(key_id).ok_or(DecryptError::UnknownKey)?.decrypt_str(text)
+    }
+
+    /// Decrypts a string with its own key and encrypts it with the current one.
+    pub fn reencrypt(&self, text: &str) -> Result<String, DecryptError> {
+        Ok(self.encrypt_str(&self.decrypt_str(text)?))
+    }
+
+    /// Appends a key and returns its index.
+    fn insert(&mut self, uni_az: UniAz) -> usize {
+        if self.get(uni_az.key_id).is_some() {
+            panic!("Key id {} is already in the key ring", uni_az.key_id);
+        }
+        self.keys.push(uni_az);
+        self.keys.len() - 1
+    }
+}
+
+/// Panics unless string output of `uni_az` records its key id.
+fn assert_envelope(uni_az: &UniAz) {
+    if !uni_az.envelope {
+        panic!("The current key of a key ring must write envelope headers");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation() {
+        let legacy = UniAz::new();
+        let v1 = legacy.encrypt_str("旧文本");
+        let first = UniAz::builder().key(b"first").key_id(1).authenticated(true).build();
+        let old = first.encrypt_str("密钥一");
+
+        let mut ring = KeyRing::new(first);
+        ring.add(legacy);
+        ring.rotate(UniAz::builder().key(b"second").key_id(2).build());
+        assert_eq!(ring.current().key_id, 2);
+        assert_eq!(ring.decrypt_str(&v1).unwrap(), "旧文本");
+        assert_eq!(ring.decrypt_str(&old).unwrap(), "密钥一");
+        assert_eq!(ring.decrypt_str(&ring.encrypt_str("新")).unwrap(), "新");
+
+        for text in [&v1, &old] {
+            let migrated = ring.reencrypt(text).unwrap();
+            assert_eq!(Envelope::parse(migrated.split(' ').next().unwrap()).unwrap().key_id, 2);
+            assert_eq!(ring.decrypt_str(&migrated), ring.decrypt_str(text));
+        }
+
+        let unknown = UniAz::builder().key(b"third").key_id(3).build();
+        assert_eq!(ring.decrypt_str(&unknown.encrypt_str("x")), Err(DecryptError::UnknownKey));
+        // Text under the wrong key for its id fails like any other mismatch.
+        let forged = UniAz::builder().key(b"forged").key_id(1).authenticated(true).build();
+        assert_eq!(
+            ring.decrypt_str(&forged.encrypt_str("x")),
+            Err(DecryptError::AuthenticationFailed)
+        );
+    }
+
+    #[test]
+    fn test_v1_with_keyed_default_id() {
+        let v1 = UniAz::new().encrypt_str("旧文本");
+        let ring = KeyRing::new(UniAz::builder().key(b"first").build());
+        assert_eq!(ring.decrypt_str(&v1).unwrap(), "旧文本");
+    }
+
+    #[test]
+    #[should_panic(expected = "Key id 1 is already in the key ring")]
+    fn test_duplicate_key_id() {
+        let mut ring = KeyRing::new(UniAz::builder().key(b"a").key_id(1).build());
+        ring.add(UniAz::builder().key(b"b").key_id(1).build());
+    }
+
+    #[test]
+    #[should_panic(expected = "must write envelope headers")]
+    fn test_current_without_envelope() {
+        KeyRing::new(UniAz::new());
+    }
+}
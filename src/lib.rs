@@ -14,9 +14,51 @@
 //! ```
 
 use std::str::FromStr;
-use crate::cipher::Cipher;
+use crate::backend::{BoxedTokenCipher, TokenCipher};
+use crate::envelope::{
+    Envelope, FLAG_COMPACT, FLAG_COMPRESSED, FLAG_FF1, FLAG_GRAPHEME, FLAG_LINES, FLAG_MAC,
+    FLAG_NONCE, FLAG_ORDER_PRESERVING, FLAG_POSITION_V1, FLAG_PRONOUNCEABLE,
+};
+use crate::key::MacKey;
 use anybase::Converter;
+use subtle::{
+    Choice, ConditionallySelectable, ConstantTimeEq, ConstantTimeGreater, ConstantTimeLess,
+};
+#[cfg(feature = "analysis")]
+pub mod analysis;
+mod backend;
+mod builder;
 mod cipher;
+mod compact;
+mod compress;
+mod ct;
+mod envelope;
+#[cfg(feature = "ff1")]
+mod ff1;
+mod id;
+mod key;
+mod keyring;
+#[cfg(feature = "normalization")]
+mod normalize;
+mod selftest;
+mod syllable;
+
+pub use crate::builder::UniAzBuilder;
+pub use crate::cipher::{AlphabetError, Cipher};
+pub use crate::compact::FrequencyTable;
+pub use crate::ct::ct_eq;
+#[cfg(feature = "ff1")]
+pub use crate::ff1::{Ff1, Ff1Error};
+pub use crate::keyring::KeyRing;
+#[cfg(feature = "normalization")]
+pub use crate::normalize::Normalization;
+pub use crate::selftest::SelfTestError;
+
+/// The Latin alphabet used as the internal cipher alphabet.
+const ALPHABET: &str = "abcdefghijklmnopqrstuvwxyz";
+
+/// Cipher iterations per token unless [`UniAzBuilder::rounds`] says otherwise.
+const DEFAULT_ROUNDS: usize = 2;
 
 /// Main interface for Unicode character encryption and decryption
 ///
@@ -31,6 +73,111 @@ pub struct UniAz {
     converter: Converter<'static>,
     rev_converter: Converter<'static>,
     cipher: Cipher,
+    /// Minimum length of tokens produced by [`encode_u64`](Self::encode_u64).
+    min_id_length: usize,
+    /// Lowercase words that [`encode_u64`](Self::encode_u64) must not produce.
+    blocklist: Vec<String>,
+    /// Whether [`encrypt_str`](Self::encrypt_str) draws a random nonce per message.
+    randomized: bool,
+    /// How string encryption derives the cipher tweak of each character token.
+    tweak_mode: TweakMode,
+    /// MAC key of the authenticated mode, if enabled.
+    mac_key: Option<MacKey>,
+    /// Key id recorded in, and required from, envelope headers.
+    key_id: u16,
+    /// Whether string output always carries an envelope header.
+    envelope: bool,
+    /// Longest character token [`decrypt`](Self::decrypt) attempts to decode.
+    max_token_length: usize,
+    /// Most characters a compressed line may expand to.
+    max_expanded_length: usize,
+    /// Whether [`decrypt`](Self::decrypt) accepts non-canonical tokens.
+    lenient: bool,
+    /// Normalization form applied to text before string encryption.
+    #[cfg(feature = "normalization")]
+    normalization: Option<Normalization>,
+    /// Whether string encryption groups character tokens by grapheme cluster.
+    #[cfg(feature = "grapheme")]
+    grapheme_clusters: bool,
+    /// Whether string encryption keeps the line structure of the text.
+    preserve_lines: bool,
+    /// How characters are written as letters before enciphering.
+    token_encoding: TokenEncoding,
+    /// Table of the compact encoding, if not the bundled one.
+    frequency_table: Option<FrequencyTable>,
+    /// Whether string encryption compresses the text into a stream token.
+    compressed: bool,
+    /// Cipher iterations per token.
+    rounds: usize,
+    /// FF1 cipher replacing [`Cipher`] for the instance's own output, if enabled.
+    ff1: Option<BoxedTokenCipher>,
+}
+
+/// How [`UniAz::encrypt_str`] derives the cipher tweak of each character token.
+///
+/// Any mode other than [`TweakMode::None`] is recorded in the envelope header,
+/// so text can be decrypted by instances configured with any mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum TweakMode {
+    /// Every character is encrypted on its own, so equal characters produce
+    /// equal tokens within a message. This is the original deterministic mode.
+    #[default]
+    None,
+    /// Version 1 of the position tweak: the index of each character in the
+    /// message is mixed into its tweak, so `"aaaa"` produces four unrelated
+    /// tokens. The mixing function is frozen; any change will get a new variant.
+    PositionV1,
+}
+
+impl TweakMode {
+    /// Derives the cipher tweak of the `index`-th character token of a message.
+    fn tweak(self, nonce: u64, index: usize) -> u64 {
+        match self {
+            TweakMode::None => nonce,
+            TweakMode::PositionV1 => position_tweak_v1(nonce, index as u64),
+        }
+    }
+}
+
+/// How a character is written as letters before it is enciphered.
+///
+/// Any encoding other than [`TokenEncoding::Radix`] is recorded in the
+/// envelope header, so [`UniAz::decrypt_str`] reads text in any encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum TokenEncoding {
+    /// The code point written in base 26: 1-5 letters, 4 for most CJK
+    /// characters and emoji. This is the original encoding.
+    #[default]
+    Radix,
+    /// The character's rank in a [`FrequencyTable`] written in bijective
+    /// base 26, so frequent characters get 1-2 letter tokens. Tokens are
+    /// space-separated, so the code need not be prefix-free and uses every
+    /// letter string as a token.
+    Compact,
+    /// The enciphered radix token rendered as 1-5 consonant-vowel syllables
+    /// in the style of proquints, such as `"duhoju"` for `'你'`, so tokens
+    /// can be read aloud. The consonants leave out `p`, `t`, `n` and `s`, so
+    /// each commonly confused pair shares one letter. Decoding reads misheard
+    /// and respelled syllables, such as `tu` or `dU` for `du`, as the syllable
+    /// they sound like, and only accepts them when the instance is
+    /// [lenient](UniAzBuilder::lenient).
+    Pronounceable,
+    /// The code point written in base 26, left-padded to 5 letters and not
+    /// enciphered, so the byte order of [`UniAz::encrypt_str`] output matches
+    /// the code point order of the source strings and encoded keys keep
+    /// working with range scans and `ORDER BY`.
+    ///
+    /// This is an encoding, not encryption: tokens reveal the code points
+    /// and the order and equality of all characters, to anyone. Building an
+    /// instance with it panics if a key, authentication, randomized nonces,
+    /// a tweak mode, compression, line preservation or grapheme grouping is
+    /// also configured, as those either have no effect or break the order.
+    /// Explicit nonces of
+    /// [`encrypt_str_with_nonce`](UniAz::encrypt_str_with_nonce) are written
+    /// but do not change the tokens.
+    OrderPreserving,
 }
 
 impl UniAz {
@@ -47,21 +194,28 @@ impl UniAz {
     /// let uni_az = UniAz::new();
     /// ```
     pub fn new() -> Self {
-        const ALPHABET: &str = "abcdefghijklmnopqrstuvwxyz";
-        let converter = Converter::new("0123456789", ALPHABET);
-        let rev_converter = converter.inverse();
+        UniAzBuilder::new().build()
+    }
 
-        UniAz {
-            converter,
-            rev_converter,
-            cipher: Cipher::new(ALPHABET),
-        }
+    /// Returns a [`UniAzBuilder`] for configuring a new instance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use uniaz::UniAz;
+    ///
+    /// let uni_az = UniAz::builder().min_id_length(8).build();
+    /// assert!(uni_az.encode_u64(1).len() >= 8);
+    /// ```
+    pub fn builder() -> UniAzBuilder {
+        UniAzBuilder::new()
     }
-    
+
     /// Encrypts a single Unicode character
     ///
     /// Takes a character, converts it to its Unicode code point, represents
-    /// that number in the internal alphabet base, then encrypts it twice.
+    /// that number in the internal alphabet base, then encrypts it with the
+    /// configured number of [rounds](UniAzBuilder::rounds).
     ///
     /// # Arguments
     ///
@@ -80,17 +234,13 @@ impl UniAz {
     /// let encrypted = uni_az.encrypt('A');
     /// ```
     pub fn encrypt(&self, plain: char) -> String {
-        let numeric = (plain as u32).to_string();
-        let converted = self
-            .converter
-            .convert(&numeric)
-            .expect("converter: valid decimal string for Unicode codepoint");
-        self.cipher.encrypt(&converted, 2)
+        self.encrypt_char(plain, 0)
     }
     
     /// Decrypts an encrypted string back to a Unicode character
     ///
-    /// Takes an encrypted string, decrypts it twice, converts the result
+    /// Takes an encrypted string, decrypts it with the configured number of
+    /// [rounds](UniAzBuilder::rounds), converts the result
     /// from the internal alphabet base back to a number, and interprets
     /// that number as a Unicode code point.
     ///
@@ -102,6 +252,8 @@ impl UniAz {
     ///
     /// `Ok(char)` with the original character, or `Err(DecryptError)` if the
     /// cipher text is invalid, corrupted, or tampered with.
+    /// Only the canonical token [`encrypt`](Self::encrypt) produces is
+    /// accepted unless the instance is [lenient](UniAzBuilder::lenient).
     ///
     /// # Examples
     ///
@@ -114,20 +266,79 @@ impl UniAz {
     /// assert_eq!(decrypted, 'A');
     /// ```
     pub fn decrypt(&self, cipher: &str) -> Result<char, DecryptError> {
+        self.decrypt_token(cipher, 0, self.token_encoding, self.scheme())
+    }
+
+    /// Decrypts a character token like [`decrypt`](Self::decrypt), in time
+    /// that depends only on the token length.
+    ///
+    /// The token is deciphered with [`Cipher::decrypt_ct`], and its digits
+    /// are read and validated without branching on them; only the outcome is
+    /// branched on. Use it where an attacker can time the decoding of tokens
+    /// under a secret key. Only [`TokenEncoding::Radix`] is supported; other
+    /// encodings return [`DecryptError::UnsupportedFormat`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use uniaz::UniAz;
+    ///
+    /// let uni_az = UniAz::builder().key(b"secret").build();
+    /// let token = uni_az.encrypt('你');
+    /// assert_eq!(uni_az.decrypt_ct(&token), Ok('你'));
+    /// ```
+    pub fn decrypt_ct(&self, cipher: &str) -> Result<char, DecryptError> {
+        if self.token_encoding != TokenEncoding::Radix || self.ff1.is_some() {
+            return Err(DecryptError::UnsupportedFormat);
+        }
+        // The ciphertext and its length are public.
         if !cipher.chars().all(|c| c.is_ascii_lowercase()) {
             return Err(DecryptError::InvalidCipherText);
         }
-        let decrypted = self.cipher.decrypt(cipher, 2);
-        let numeric = self
-            .rev_converter
-            .convert(&decrypted)
-            .map_err(|_| DecryptError::InvalidToken)?;
-        let cp = u32::from_str(&numeric).map_err(|_| DecryptError::InvalidToken)?;
-        char::from_u32(cp).ok_or(DecryptError::InvalidCodepoint)
+        if cipher.len() > self.max_token_length {
+            return Err(DecryptError::TokenTooLong);
+        }
+        if cipher.is_empty() {
+            return self.decrypt(cipher);
+        }
+        let digits = self.cipher.decrypt_ct(cipher, self.rounds, 0);
+        let digits = digits.as_bytes();
+
+        // Values of 2^32 and above only need to be told apart as too large.
+        const LIMIT: u64 = 1 << 32;
+        let mut value = 0u64;
+        for &digit in digits {
+            let next = value * ALPHABET.len() as u64 + u64::from(digit - b'a');
+            value = u64::conditional_select(&next, &LIMIT, next.ct_gt(&LIMIT));
+        }
+        let canonical = Choice::from((digits.len() == 1) as u8) | !digits[0].ct_eq(&b'a');
+        let in_range = value.ct_lt(&LIMIT);
+        let surrogate = value.ct_gt(&0xD7FF) & value.ct_lt(&0xE000);
+        let scalar = value.ct_lt(&0x11_0000) & !surrogate;
+
+        if !self.lenient && !bool::from(canonical) {
+            return Err(DecryptError::NonCanonical);
+        }
+        if !bool::from(in_range) {
+            return Err(DecryptError::InvalidToken);
+        }
+        if !bool::from(scalar) {
+            return Err(DecryptError::InvalidCodepoint);
+        }
+        Ok(char::from_u32(value as u32).expect("checked to be a scalar value"))
     }
 
     /// Encrypts a string by encrypting each character and joining with spaces.
     ///
+    /// If the instance was built with
+    /// [`randomized`](UniAzBuilder::randomized), a fresh random nonce is drawn
+    /// and the output is that of [`encrypt_str_with_nonce`](Self::encrypt_str_with_nonce).
+    ///
+    /// # Panics
+    ///
+    /// In randomized mode, panics if the operating system random number
+    /// generator is unavailable.
+    ///
     /// # Examples
     ///
     /// ```
@@ -139,26 +350,689 @@ impl UniAz {
     /// assert_eq!(decrypted, "你好");
     /// ```
     pub fn encrypt_str(&self, text: &str) -> String {
-        text.chars()
-            .map(|c| self.encrypt(c))
-            .collect::<Vec<_>>()
-            .join(" ")
+        if self.randomized {
+            let nonce = getrandom::u64().expect("operating system random number generator unavailable");
+            return self.encrypt_str_with_nonce(text, nonce);
+        }
+        self.encrypt_message(text, None)
+    }
+
+    /// Encrypts a string under an explicit per-message nonce.
+    ///
+    /// The nonce is emitted as a 14-letter token right after the envelope
+    /// header, followed by one token per character. Every character token is
+    /// encrypted with the nonce mixed into its cipher tweak, so the same text
+    /// encrypts to unrelated tokens under different nonces.
+    ///
+    /// Randomized instances pick the nonce themselves; this method exists so
+    /// that tests and reproducible pipelines can fix it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use uniaz::UniAz;
+    ///
+    /// let uni_az = UniAz::new();
+    /// let first = uni_az.encrypt_str_with_nonce("的的", 1);
+    /// let second = uni_az.encrypt_str_with_nonce("的的", 2);
+    /// assert_ne!(first, second);
+    /// assert_eq!(uni_az.decrypt_str(&first).unwrap(), "的的");
+    /// assert_eq!(uni_az.decrypt_str(&second).unwrap(), "的的");
+    /// ```
+    pub fn encrypt_str_with_nonce(&self, text: &str, nonce: u64) -> String {
+        self.encrypt_message(text, Some(nonce))
     }
 
     /// Decrypts a string that was encrypted with [`encrypt_str`](Self::encrypt_str).
     ///
-    /// Expects space-separated encrypted tokens. Returns an error if any token is invalid.
+    /// Expects space-separated encrypted tokens. The format is detected from
+    /// the text itself: if it starts with an envelope header, the nonce, tweak
+    /// mode and tag recorded there are used; otherwise the tokens are decoded
+    /// with the frozen headerless v1 format, which is never keyed, so keyed
+    /// instances read it as well. Returns an error if any token is
+    /// invalid, or if the header names an unsupported format or another key id.
+    ///
+    /// Text grouped by grapheme cluster is split at the `-` group separator;
+    /// decoding it does not need the `grapheme` feature.
+    ///
+    /// The result is exactly the text that was encrypted. If the encrypting
+    /// instance normalized its input (the `normalization` feature), that is the
+    /// normalized form, not necessarily the original code points.
+    ///
+    /// In [authenticated](UniAzBuilder::authenticated) mode the trailing tag is
+    /// verified first, and [`DecryptError::AuthenticationFailed`] is returned
+    /// before any token is decrypted if it is missing or does not match.
     pub fn decrypt_str(&self, text: &str) -> Result<String, DecryptError> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let envelope = match tokens.first() {
+            Some(header) if Envelope::is_header(header) => Envelope::parse(header)?,
+            _ => {
+                if self.mac_key.is_some() {
+                    return Err(DecryptError::AuthenticationFailed);
+                }
+                // Only unkeyed instances write v1 text.
+                let v1 = Cipher::new(ALPHABET);
+                let scheme = Scheme { cipher: &v1, rounds: DEFAULT_ROUNDS };
+                return tokens
+                    .iter()
+                    .map(|token| self.decrypt_token(token, 0, TokenEncoding::Radix, scheme))
+                    .collect();
+            }
+        };
+        if envelope.key_id != self.key_id {
+            return Err(DecryptError::UnknownKey);
+        }
+        if envelope.has(FLAG_LINES) {
+            return self.decrypt_lines(text, &envelope);
+        }
+
+        let tokens = match (&self.mac_key, envelope.has(FLAG_MAC)) {
+            (None, false) => &tokens[..],
+            (Some(mac_key), true) => {
+                let (tag, tokens) = tokens.split_last().ok_or(DecryptError::AuthenticationFailed)?;
+                let tag_value = mac_key.tag(tokens.iter().copied());
+                let scheme = self.envelope_scheme(&envelope)?;
+                let expected = self.encrypt_fixed(tag_value, U64_WIDTH, scheme);
+                if !ct_eq(&expected, tag) {
+                    return Err(DecryptError::AuthenticationFailed);
+                }
+                tokens
+            }
+            _ => return Err(DecryptError::AuthenticationFailed),
+        };
+
+        let (nonce, body) = self.read_nonce(&envelope, &tokens[1..])?;
+        self.decrypt_tokens(&envelope, nonce, body, &mut 0)
+    }
+
+    /// Encrypts arbitrary bytes into a letters-only string.
+    ///
+    /// The input is split into chunks of up to 4 bytes. Each chunk is read as a
+    /// big-endian number, written in the internal alphabet base with a fixed
+    /// width that depends only on the chunk length, and encrypted with the
+    /// configured number of [rounds](UniAzBuilder::rounds). The
+    /// chunks are concatenated without separators, so the output contains only
+    /// a-z and can carry hashes, keys or non-UTF-8 data.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use uniaz::UniAz;
+    ///
+    /// let uni_az = UniAz::new();
+    /// let encrypted = uni_az.encrypt_bytes(b"\xff\x00binary");
+    /// assert!(encrypted.chars().all(|c| c.is_ascii_lowercase()));
+    /// assert_eq!(uni_az.decrypt_bytes(&encrypted).unwrap(), b"\xff\x00binary");
+    /// ```
+    pub fn encrypt_bytes(&self, bytes: &[u8]) -> String {
         let mut result = String::new();
-        for token in text.split_whitespace() {
-            if token.is_empty() {
-                continue;
+        for chunk in bytes.chunks(BYTE_CHUNK_SIZE) {
+            let value = chunk.iter().fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+            let width = self.byte_chunk_widths()[chunk.len()];
+            result.push_str(&self.encrypt_fixed(value, width, self.scheme()));
+        }
+        result
+    }
+
+    /// Decrypts a string that was encrypted with [`encrypt_bytes`](Self::encrypt_bytes).
+    ///
+    /// Returns an error if the input contains anything other than a-z, has a
+    /// length that no byte sequence encodes to, or contains a chunk that does
+    /// not decode to a value fitting its byte count.
+    pub fn decrypt_bytes(&self, text: &str) -> Result<Vec<u8>, DecryptError> {
+        if !text.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(DecryptError::InvalidCipherText);
+        }
+        let widths = self.byte_chunk_widths();
+        let full_width = widths[BYTE_CHUNK_SIZE];
+        let tail_width = text.len() % full_width;
+        let tail_len = widths
+            .iter()
+            .position(|&w| w == tail_width)
+            .ok_or(DecryptError::InvalidToken)?;
+
+        // The text is ASCII-only, so slicing on byte offsets is safe.
+        let (body, tail) = text.split_at(text.len() - tail_width);
+        let chunks = (0..body.len())
+            .step_by(full_width)
+            .map(|start| (&body[start..start + full_width], BYTE_CHUNK_SIZE))
+            .chain((tail_len > 0).then_some((tail, tail_len)));
+
+        let mut result = Vec::with_capacity(body.len() / full_width * BYTE_CHUNK_SIZE + tail_len);
+        for (token, len) in chunks {
+            let value = self.decrypt_fixed(token, self.scheme())?;
+            if value >> (8 * len) != 0 {
+                return Err(DecryptError::InvalidToken);
             }
-            let c = self.decrypt(token)?;
-            result.push(c);
+            result.extend_from_slice(&value.to_be_bytes()[8 - len..]);
         }
         Ok(result)
     }
+
+    /// Decrypts a message written in the line-preserving layout.
+    ///
+    /// The first line holds the header and nonce, every following line one
+    /// line of plain text, and the tag, if any, a line of its own. Tokens must
+    /// be separated by exactly one space.
+    fn decrypt_lines(&self, text: &str, envelope: &Envelope) -> Result<String, DecryptError> {
+        let text = match (&self.mac_key, envelope.has(FLAG_MAC)) {
+            (None, false) => text,
+            (Some(mac_key), true) => {
+                let (text, tag) = text.rsplit_once('\n').ok_or(DecryptError::AuthenticationFailed)?;
+                let tag_value = mac_key.tag([text]);
+                let scheme = self.envelope_scheme(envelope)?;
+                let expected = self.encrypt_fixed(tag_value, U64_WIDTH, scheme);
+                if !ct_eq(&expected, tag) {
+                    return Err(DecryptError::AuthenticationFailed);
+                }
+                text
+            }
+            _ => return Err(DecryptError::AuthenticationFailed),
+        };
+
+        let mut lines = text.split('\n');
+        let preamble = split_line(lines.next().unwrap_or_default())?;
+        // The header must open the first line, not a later one.
+        let Some((header, preamble)) = preamble.split_first() else {
+            return Err(DecryptError::InvalidCipherText);
+        };
+        if !Envelope::is_header(header) {
+            return Err(DecryptError::InvalidCipherText);
+        }
+        let (nonce, rest) = self.read_nonce(envelope, preamble)?;
+        if !rest.is_empty() {
+            return Err(DecryptError::InvalidToken);
+        }
+        let mut index = 0;
+        let lines = lines
+            .map(|line| self.decrypt_tokens(envelope, nonce, &split_line(line)?, &mut index))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(lines.join("\n"))
+    }
+
+    /// Reads the nonce token following the header, if the envelope has one.
+    ///
+    /// Returns the nonce (0 without one) and the tokens after it.
+    fn read_nonce<'a>(
+        &self,
+        envelope: &Envelope,
+        tokens: &'a [&'a str],
+    ) -> Result<(u64, &'a [&'a str]), DecryptError> {
+        if !envelope.has(FLAG_NONCE) {
+            return Ok((0, tokens));
+        }
+        let (token, rest) = tokens.split_first().ok_or(DecryptError::InvalidToken)?;
+        if !token.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(DecryptError::InvalidCipherText);
+        }
+        if token.len() > U64_WIDTH {
+            return Err(DecryptError::TokenTooLong);
+        }
+        Ok((self.decrypt_fixed(token, self.envelope_scheme(envelope)?)?, rest))
+    }
+
+    /// Decrypts character tokens of an enveloped message.
+    ///
+    /// `index` is the position of the first character in the message and is
+    /// advanced past the decrypted ones.
+    fn decrypt_tokens(
+        &self,
+        envelope: &Envelope,
+        nonce: u64,
+        tokens: &[&str],
+        index: &mut usize,
+    ) -> Result<String, DecryptError> {
+        let tweak_mode = if envelope.has(FLAG_POSITION_V1) {
+            TweakMode::PositionV1
+        } else {
+            TweakMode::None
+        };
+        let scheme = self.envelope_scheme(envelope)?;
+        if envelope.has(FLAG_COMPRESSED) {
+            return self.decrypt_stream(tokens, nonce, tweak_mode, scheme, index);
+        }
+        let encodings = FLAG_COMPACT | FLAG_PRONOUNCEABLE | FLAG_ORDER_PRESERVING;
+        let encoding = match envelope.flags & encodings {
+            0 => TokenEncoding::Radix,
+            FLAG_COMPACT => TokenEncoding::Compact,
+            FLAG_PRONOUNCEABLE => TokenEncoding::Pronounceable,
+            FLAG_ORDER_PRESERVING => TokenEncoding::OrderPreserving,
+            _ => return Err(DecryptError::UnsupportedFormat),
+        };
+        let grouped = envelope.has(FLAG_GRAPHEME);
+        tokens
+            .iter()
+            .flat_map(|group| group.split(move |c| grouped && c == GROUP_SEPARATOR))
+            .map(|token| {
+                let i = *index;
+                *index += 1;
+                match token {
+                    "" => Err(DecryptError::InvalidToken),
+                    _ => {
+                        let tweak = tweak_mode.tweak(nonce, i);
+                        self.decrypt_token(token, tweak, encoding, scheme)
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Deciphers and expands the stream token of a compressed line.
+    ///
+    /// `index` counts stream blocks, each of which was enciphered with its
+    /// own tweak.
+    fn decrypt_stream(
+        &self,
+        tokens: &[&str],
+        nonce: u64,
+        tweak_mode: TweakMode,
+        scheme: Scheme<'_>,
+        index: &mut usize,
+    ) -> Result<String, DecryptError> {
+        let token = match tokens {
+            [] => return Ok(String::new()),
+            [token] => token,
+            _ => return Err(DecryptError::InvalidToken),
+        };
+        if !token.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(DecryptError::InvalidCipherText);
+        }
+        let mut stream = String::with_capacity(token.len());
+        // The token is ASCII-only, so byte chunks are valid strings.
+        for block in token.as_bytes().chunks(STREAM_BLOCK) {
+            let block = std::str::from_utf8(block).expect("stream is ASCII");
+            if !(scheme.cipher.min_len()..=scheme.cipher.max_len()).contains(&block.len()) {
+                return Err(DecryptError::InvalidToken);
+            }
+            let tweak = tweak_mode.tweak(nonce, *index);
+            stream.push_str(&scheme.decrypt(block, tweak));
+            *index += 1;
+        }
+        compress::expand(&stream, self.frequency_table(), self.max_expanded_length)
+    }
+
+    /// Assembles an enveloped or v1 message.
+    ///
+    /// The envelope header is written whenever the output differs from the
+    /// v1 format or the instance asks for it; v1 output is exactly the
+    /// space-joined character tokens.
+    fn encrypt_message(&self, text: &str, nonce: Option<u64>) -> String {
+        #[cfg(feature = "normalization")]
+        let text = &*match self.normalization {
+            Some(form) => form.apply(text),
+            None => std::borrow::Cow::Borrowed(text),
+        };
+
+        let mut flags = match self.tweak_mode {
+            TweakMode::None => 0,
+            TweakMode::PositionV1 => FLAG_POSITION_V1,
+        };
+        if nonce.is_some() {
+            flags |= FLAG_NONCE;
+        }
+        if self.mac_key.is_some() {
+            flags |= FLAG_MAC;
+        }
+        #[cfg(feature = "grapheme")]
+        if self.grapheme_clusters {
+            flags |= FLAG_GRAPHEME;
+        }
+        if self.preserve_lines {
+            flags |= FLAG_LINES;
+        }
+        match self.token_encoding {
+            TokenEncoding::Radix => {}
+            TokenEncoding::Compact => flags |= FLAG_COMPACT,
+            TokenEncoding::Pronounceable => flags |= FLAG_PRONOUNCEABLE,
+            TokenEncoding::OrderPreserving => flags |= FLAG_ORDER_PRESERVING,
+        }
+        if self.compressed {
+            flags |= FLAG_COMPRESSED;
+        }
+        if self.ff1.is_some() {
+            flags |= FLAG_FF1;
+        }
+
+        let mut tokens = Vec::new();
+        if self.envelope || flags != 0 || self.rounds != DEFAULT_ROUNDS {
+            let version = match self.rounds {
+                DEFAULT_ROUNDS => envelope::VERSION,
+                _ => envelope::VERSION_ROUNDS,
+            };
+            let envelope = Envelope {
+                version,
+                alphabet_id: envelope::ALPHABET_LATIN,
+                flags,
+                key_id: self.key_id,
+                rounds: self.rounds as u8,
+            };
+            tokens.push(envelope.encode());
+        }
+        if let Some(nonce) = nonce {
+            tokens.push(self.encrypt_fixed(nonce, U64_WIDTH, self.scheme()));
+        }
+        let nonce = nonce.unwrap_or(0);
+        let mut index = 0;
+
+        if !self.preserve_lines {
+            tokens.extend(self.encrypt_line(text, nonce, &mut index));
+            if let Some(mac_key) = &self.mac_key {
+                let tag = mac_key.tag(tokens.iter().map(String::as_str));
+                tokens.push(self.encrypt_fixed(tag, U64_WIDTH, self.scheme()));
+            }
+            return tokens.join(" ");
+        }
+
+        let mut message = tokens.join(" ");
+        for line in text.split('\n') {
+            message.push('\n');
+            message.push_str(&self.encrypt_line(line, nonce, &mut index).join(" "));
+        }
+        if let Some(mac_key) = &self.mac_key {
+            let tag = mac_key.tag([message.as_str()]);
+            message.push('\n');
+            message.push_str(&self.encrypt_fixed(tag, U64_WIDTH, self.scheme()));
+        }
+        message
+    }
+
+    /// Encrypts the characters of a message, starting at position `index`.
+    ///
+    /// Returns one token per character, or one group per grapheme cluster in
+    /// grouped mode, and advances `index` past the encrypted characters.
+    /// Compressed text is a single stream token instead, and `index` then
+    /// counts its blocks.
+    fn encrypt_line(&self, text: &str, nonce: u64, index: &mut usize) -> Vec<String> {
+        if self.compressed {
+            let stream = compress::compress(text, self.frequency_table());
+            if stream.is_empty() {
+                return Vec::new();
+            }
+            let mut token = String::with_capacity(stream.len());
+            for block in stream.as_bytes().chunks(STREAM_BLOCK) {
+                let block = std::str::from_utf8(block).expect("stream is ASCII");
+                let tweak = self.tweak_mode.tweak(nonce, *index);
+                token.push_str(&self.scheme().encrypt(block, tweak));
+                *index += 1;
+            }
+            return vec![token];
+        }
+
+        let first = *index;
+        *index += text.chars().count();
+
+        let mut tokens = Vec::new();
+        #[cfg_attr(not(feature = "grapheme"), allow(unused_mut))]
+        let mut char_tokens = text
+            .chars()
+            .enumerate()
+            .map(|(i, c)| self.encrypt_char(c, self.tweak_mode.tweak(nonce, first + i)));
+        #[cfg(feature = "grapheme")]
+        if self.grapheme_clusters {
+            use unicode_segmentation::UnicodeSegmentation;
+
+            for cluster in text.graphemes(true) {
+                let mut group = String::new();
+                for token in char_tokens.by_ref().take(cluster.chars().count()) {
+                    if !group.is_empty() {
+                        group.push(GROUP_SEPARATOR);
+                    }
+                    group.push_str(&token);
+                }
+                tokens.push(group);
+            }
+        }
+        tokens.extend(char_tokens);
+        tokens
+    }
+
+    /// Encrypts a character with the given cipher tweak.
+    fn encrypt_char(&self, plain: char, tweak: u64) -> String {
+        let converted = match self.token_encoding {
+            TokenEncoding::Radix
+            | TokenEncoding::Pronounceable
+            | TokenEncoding::OrderPreserving => {
+                let numeric = (plain as u32).to_string();
+                self.converter
+                    .convert(&numeric)
+                    .expect("converter: valid decimal string for Unicode codepoint")
+            }
+            TokenEncoding::Compact => self.frequency_table().encode(plain),
+        };
+        if self.token_encoding == TokenEncoding::OrderPreserving {
+            return format!("{converted:a>ORDERED_WIDTH$}");
+        }
+        let scheme = self.scheme();
+        let min_len = scheme.cipher.min_len();
+        let converted = if converted.len() < min_len {
+            format!("{converted:a>min_len$}")
+        } else {
+            converted
+        };
+        let token = scheme.encrypt(&converted, tweak);
+        match self.token_encoding {
+            TokenEncoding::Pronounceable => syllable::render(&token),
+            _ => token,
+        }
+    }
+
+    /// Decrypts a character token with the tweak, encoding and scheme it was
+    /// encrypted with.
+    fn decrypt_token(
+        &self,
+        cipher: &str,
+        tweak: u64,
+        encoding: TokenEncoding,
+        scheme: Scheme<'_>,
+    ) -> Result<char, DecryptError> {
+        let letters;
+        let cipher = match encoding {
+            TokenEncoding::Pronounceable => {
+                letters = syllable::parse(cipher, self.lenient)?;
+                letters.as_str()
+            }
+            _ => cipher,
+        };
+        if !cipher.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(DecryptError::InvalidCipherText);
+        }
+        // Decryption is quadratic in the token length, so refuse long tokens up front.
+        if cipher.len() > self.max_token_length.min(scheme.cipher.max_len()) {
+            return Err(DecryptError::TokenTooLong);
+        }
+        // No character encrypts to an empty token, and ciphers with a minimum
+        // length pad every token to it.
+        if cipher.len() < scheme.cipher.min_len().max(1) {
+            return Err(DecryptError::InvalidToken);
+        }
+        let decrypted = match encoding {
+            // Padded tokens are never enciphered, so they are canonical by width.
+            TokenEncoding::OrderPreserving if cipher.len() == ORDERED_WIDTH => cipher.to_string(),
+            TokenEncoding::OrderPreserving => return Err(DecryptError::InvalidToken),
+            _ => scheme.decrypt(cipher, tweak),
+        };
+        if encoding == TokenEncoding::Compact {
+            // Bijective numeration has no padding, so every token is canonical.
+            return self.frequency_table().decode(&decrypted);
+        }
+        // The canonical token of a character is the cipher of its digits
+        // without leading zeros, or padded to the cipher's minimum length;
+        // the cipher is a permutation, so each character then has exactly
+        // one token.
+        let padded = encoding == TokenEncoding::OrderPreserving
+            || decrypted.len() == scheme.cipher.min_len();
+        if !self.lenient && !padded && !is_canonical(&decrypted) {
+            return Err(DecryptError::NonCanonical);
+        }
+        let numeric = self
+            .rev_converter
+            .convert(&decrypted)
+            .map_err(|_| DecryptError::InvalidToken)?;
+        let cp = u32::from_str(&numeric).map_err(|_| DecryptError::InvalidToken)?;
+        char::from_u32(cp).ok_or(DecryptError::InvalidCodepoint)
+    }
+
+    /// The scheme of the instance's own output.
+    fn scheme(&self) -> Scheme<'_> {
+        let cipher: &dyn TokenCipher = match &self.ff1 {
+            Some(ff1) => ff1.as_ref(),
+            None => &self.cipher,
+        };
+        Scheme { cipher, rounds: self.rounds }
+    }
+
+    /// The scheme recorded in an envelope header.
+    ///
+    /// Returns [`DecryptError::UnsupportedFormat`] unless the text and the
+    /// instance agree on whether tokens are enciphered with FF1.
+    fn envelope_scheme(&self, envelope: &Envelope) -> Result<Scheme<'_>, DecryptError> {
+        let cipher: &dyn TokenCipher = match (&self.ff1, envelope.has(FLAG_FF1)) {
+            (None, false) => &self.cipher,
+            (Some(ff1), true) => ff1.as_ref(),
+            _ => return Err(DecryptError::UnsupportedFormat),
+        };
+        Ok(Scheme { cipher, rounds: envelope.rounds() })
+    }
+
+    /// Widths of byte chunks of [`encrypt_bytes`](Self::encrypt_bytes).
+    fn byte_chunk_widths(&self) -> &'static [usize; BYTE_CHUNK_SIZE + 1] {
+        if self.scheme().cipher.min_len() > BYTE_CHUNK_WIDTHS[1] {
+            &PADDED_BYTE_CHUNK_WIDTHS
+        } else {
+            &BYTE_CHUNK_WIDTHS
+        }
+    }
+
+    /// Table of the compact encoding: the configured one or the bundled one.
+    fn frequency_table(&self) -> &FrequencyTable {
+        self.frequency_table.as_ref().unwrap_or_else(|| FrequencyTable::bundled_ref())
+    }
+
+    /// Encrypts a number as a token of at least `width` letters.
+    ///
+    /// The number is written in the internal alphabet base, left-padded with
+    /// the zero digit up to `width` and the cipher's minimum length, and
+    /// encrypted with the given scheme.
+    fn encrypt_fixed(&self, value: u64, width: usize, scheme: Scheme<'_>) -> String {
+        let converted = self
+            .converter
+            .convert(&value.to_string())
+            .expect("converter: valid decimal string for u64");
+        let width = width.max(scheme.cipher.min_len());
+        let padded = format!("{}{converted}", "a".repeat(width.saturating_sub(converted.len())));
+        scheme.encrypt(&padded, 0)
+    }
+
+    /// Decrypts a fixed-width token produced by [`encrypt_fixed`](Self::encrypt_fixed).
+    fn decrypt_fixed(&self, token: &str, scheme: Scheme<'_>) -> Result<u64, DecryptError> {
+        if !(scheme.cipher.min_len()..=scheme.cipher.max_len()).contains(&token.len()) {
+            return Err(DecryptError::InvalidToken);
+        }
+        let decrypted = scheme.decrypt(token, 0);
+        let numeric = self
+            .rev_converter
+            .convert(&decrypted)
+            .map_err(|_| DecryptError::InvalidToken)?;
+        u64::from_str(&numeric).map_err(|_| DecryptError::InvalidToken)
+    }
+}
+
+/// Checks that base-26 digits have no leading zero digit, as the converter writes them.
+fn is_canonical(digits: &str) -> bool {
+    match digits.as_bytes() {
+        [] => false,
+        [_] => true,
+        [first, ..] => *first != ALPHABET.as_bytes()[0],
+    }
+}
+
+/// Splits a line of the line-preserving layout at single spaces.
+///
+/// Empty tokens, i.e. leading, trailing or repeated spaces, are rejected, so
+/// every message has exactly one accepted layout.
+fn split_line(line: &str) -> Result<Vec<&str>, DecryptError> {
+    if line.is_empty() {
+        return Ok(Vec::new());
+    }
+    let tokens: Vec<&str> = line.split(' ').collect();
+    if tokens.contains(&"") {
+        return Err(DecryptError::InvalidCipherText);
+    }
+    Ok(tokens)
+}
+
+/// Mixes a character index into the message nonce for [`TweakMode::PositionV1`].
+///
+/// This is the SplitMix64 finalizer applied to the nonce offset by the index
+/// times the golden-ratio increment. The output is part of the stored format
+/// and must never change.
+fn position_tweak_v1(nonce: u64, index: u64) -> u64 {
+    let mut z = nonce.wrapping_add(index.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Number of letters needed to write `u64::MAX` in base 26.
+const U64_WIDTH: usize = 14;
+
+/// Width of [`TokenEncoding::OrderPreserving`] tokens: enough letters to
+/// write `char::MAX` in base 26.
+const ORDERED_WIDTH: usize = 5;
+
+/// Letters per independently enciphered block of a compressed stream.
+///
+/// Enciphering costs grow with the square of the input length, so long
+/// streams are split into blocks that each fit a 64-bit seed.
+const STREAM_BLOCK: usize = 12;
+
+/// Joins the character tokens of one grapheme cluster in grouped output.
+const GROUP_SEPARATOR: char = '-';
+
+/// Number of bytes packed into one chunk by [`UniAz::encrypt_bytes`].
+const BYTE_CHUNK_SIZE: usize = 4;
+
+/// Token width in letters for a byte chunk of each length (0..=4 bytes).
+///
+/// Each width is the smallest one whose 26-letter range covers all values of
+/// that many bytes. The widths are pairwise distinct and only the full-chunk
+/// width is odd, so the length of the tail chunk identifies its byte count.
+const BYTE_CHUNK_WIDTHS: [usize; BYTE_CHUNK_SIZE + 1] = [0, 2, 4, 6, 7];
+
+/// Chunk widths for ciphers that take at least 5 letters, such as FF1;
+/// they are still distinct modulo the full width.
+const PADDED_BYTE_CHUNK_WIDTHS: [usize; BYTE_CHUNK_SIZE + 1] = [0, 5, 6, 7, 8];
+
+/// The cipher and round count the tokens of a message are enciphered with.
+#[derive(Clone, Copy)]
+struct Scheme<'a> {
+    cipher: &'a dyn TokenCipher,
+    rounds: usize,
+}
+
+impl Scheme<'_> {
+    fn encrypt(&self, input: &str, tweak: u64) -> String {
+        self.cipher.encipher(input, self.rounds, tweak)
+    }
+
+    fn decrypt(&self, input: &str, tweak: u64) -> String {
+        self.cipher.decipher(input, self.rounds, tweak)
+    }
+}
+
+impl std::fmt::Debug for UniAz {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The cipher alphabet and MAC key are key material, so only the
+        // configuration is shown.
+        f.debug_struct("UniAz")
+            .field("key_id", &self.key_id)
+            .field("authenticated", &self.mac_key.is_some())
+            .field("tweak_mode", &self.tweak_mode)
+            .field("token_encoding", &self.token_encoding)
+            .field("rounds", &self.rounds)
+            .field("ff1", &self.ff1.is_some())
+            .finish_non_exhaustive()
+    }
 }
 
 impl Default for UniAz {
@@ -168,7 +1042,10 @@ impl Default for UniAz {
 }
 
 /// Error type for decryption failures.
+///
+/// New variants may be added as formats evolve, so matches need a wildcard arm.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum DecryptError {
     /// The cipher text contains invalid characters (expects only a-z).
     InvalidCipherText,
@@ -176,6 +1053,19 @@ pub enum DecryptError {
     InvalidToken,
     /// The decoded value is not a valid Unicode codepoint.
     InvalidCodepoint,
+    /// The message tag is missing or does not match (wrong key or tampered text).
+    AuthenticationFailed,
+    /// The envelope header names a format version, alphabet or mode this
+    /// version of the crate does not support.
+    UnsupportedFormat,
+    /// The envelope header names a key id other than the instance's.
+    UnknownKey,
+    /// A token is longer than any valid token, or a compressed line expands
+    /// past the configured limit, so it was rejected.
+    TokenTooLong,
+    /// The token decodes to a character but is not the token
+    /// [`UniAz::encrypt`] produces for it.
+    NonCanonical,
 }
 
 impl std::fmt::Display for DecryptError {
@@ -184,6 +1074,11 @@ impl std::fmt::Display for DecryptError {
             DecryptError::InvalidCipherText => write!(f, "cipher text must contain only a-z"),
             DecryptError::InvalidToken => write!(f, "invalid or corrupted cipher token"),
             DecryptError::InvalidCodepoint => write!(f, "decoded value is not a valid Unicode codepoint"),
+            DecryptError::AuthenticationFailed => write!(f, "message authentication failed"),
+            DecryptError::UnsupportedFormat => write!(f, "unsupported envelope format"),
+            DecryptError::UnknownKey => write!(f, "message was encrypted under an unknown key id"),
+            DecryptError::TokenTooLong => write!(f, "cipher token is longer than any valid token"),
+            DecryptError::NonCanonical => write!(f, "cipher token is not in canonical form"),
         }
     }
 }
@@ -217,6 +1112,76 @@ mod tests {
         assert!(u.decrypt("ab12").is_err());
     }
 
+    #[test]
+    fn test_decrypt_empty() {
+        use crate::DecryptError;
+
+        let lenient = UniAz::builder().lenient(true).build();
+        for u in [UniAz::new(), lenient] {
+            assert_eq!(u.decrypt(""), Err(DecryptError::InvalidToken));
+            assert_eq!(u.decrypt_ct(""), Err(DecryptError::InvalidToken));
+        }
+    }
+
+    #[test]
+    fn test_token_too_long() {
+        use crate::DecryptError;
+
+        let u = UniAz::new();
+        let long = "q".repeat(100_000);
+        assert_eq!(u.decrypt("qqqqqq"), Err(DecryptError::TokenTooLong));
+        assert_eq!(u.decrypt(&long), Err(DecryptError::TokenTooLong));
+        assert_eq!(u.decrypt_str(&format!("abpx {long}")), Err(DecryptError::TokenTooLong));
+        assert_eq!(
+            u.decrypt_str(&format!("uzcaaabaaaa {long} abpx")),
+            Err(DecryptError::TokenTooLong)
+        );
+        // The longest valid tokens are still accepted.
+        assert_eq!(u.decrypt(&u.encrypt(char::MAX)), Ok(char::MAX));
+
+        let short = UniAz::builder().max_token_length(3).build();
+        assert_eq!(short.decrypt(&u.encrypt('你')), Err(DecryptError::TokenTooLong));
+        assert_eq!(short.decrypt(&u.encrypt('a')), Ok('a'));
+    }
+
+    #[test]
+    fn test_canonical_tokens() {
+        use crate::DecryptError;
+        use std::collections::HashMap;
+
+        // Every token of up to 3 letters, and every 7th of 4 letters, has at
+        // most one preimage, and it is the token `encrypt` produces.
+        let u = UniAz::new();
+        let mut seen = HashMap::new();
+        let mut token = String::new();
+        for len in 1..=4u32 {
+            let step = if len == 4 { 7 } else { 1 };
+            for n in (0..26u32.pow(len)).step_by(step) {
+                token.clear();
+                for i in (0..len).rev() {
+                    token.push((b'a' + (n / 26u32.pow(i) % 26) as u8) as char);
+                }
+                match u.decrypt(&token) {
+                    Ok(c) => {
+                        assert_eq!(u.encrypt(c), token);
+                        assert!(seen.insert(c, token.clone()).is_none(), "{c:?} has two tokens");
+                    }
+                    Err(err) => assert!(matches!(
+                        err,
+                        DecryptError::NonCanonical | DecryptError::InvalidCodepoint
+                    )),
+                }
+            }
+        }
+
+        // A padded token decodes to the same character and is rejected unless lenient.
+        let padded = u.cipher.encrypt(&format!("a{}", u.converter.convert("20320").unwrap()), 2);
+        assert_eq!(u.decrypt(&padded), Err(DecryptError::NonCanonical));
+        let lenient = UniAz::builder().lenient(true).build();
+        assert_eq!(lenient.decrypt(&padded), Ok('你'));
+        assert_eq!(lenient.decrypt(&u.encrypt('你')), Ok('你'));
+    }
+
     #[test]
     fn test_encrypt_decrypt_str() {
         let u = UniAz::new();
@@ -226,15 +1191,506 @@ mod tests {
     }
 
     #[test]
-    #[ignore = "takes ~minutes to run all 1.1M codepoints"]
-    fn test_all_codepoints() {
+    fn test_randomized_str() {
+        let u = UniAz::builder().randomized(true).build();
+        let first = u.encrypt_str("的的的");
+        let second = u.encrypt_str("的的的");
+        assert_ne!(first, second);
+        assert_eq!(u.decrypt_str(&first).unwrap(), "的的的");
+        assert_eq!(u.decrypt_str(&second).unwrap(), "的的的");
+        // Plain instances read randomized output too.
+        assert_eq!(UniAz::new().decrypt_str(&first).unwrap(), "的的的");
+    }
+
+    #[test]
+    fn test_nonce_str() {
+        let u = UniAz::new();
+        let encrypted = u.encrypt_str_with_nonce("你好", 42);
+        assert_eq!(encrypted, u.encrypt_str_with_nonce("你好", 42));
+        assert_ne!(encrypted, u.encrypt_str_with_nonce("你好", 43));
+        let tokens: Vec<&str> = encrypted.split(' ').collect();
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(tokens[1].len(), 14);
+        assert_eq!(u.decrypt_str(&encrypted).unwrap(), "你好");
+        assert_eq!(u.decrypt_str(&u.encrypt_str_with_nonce("", u64::MAX)).unwrap(), "");
+        // A tampered nonce changes every character or fails to decode.
+        let tampered = encrypted.replace(tokens[1], "zzzzzzzzzzzzzz");
+        assert_ne!(u.decrypt_str(&tampered).ok().as_deref(), Some("你好"));
+        // The nonce token announced by the header must be present.
+        assert!(u.decrypt_str(tokens[0]).is_err());
+    }
+
+    #[test]
+    fn test_position_tweak() {
+        use crate::TweakMode;
+
+        let u = UniAz::builder().tweak_mode(TweakMode::PositionV1).build();
+        let encrypted = u.encrypt_str("aaaa");
+        let tokens: std::collections::HashSet<_> = encrypted.split(' ').skip(1).collect();
+        assert_eq!(tokens.len(), 4);
+        assert_eq!(u.decrypt_str(&encrypted).unwrap(), "aaaa");
+        assert_eq!(UniAz::new().decrypt_str(&encrypted).unwrap(), "aaaa");
+
+        let with_nonce = u.encrypt_str_with_nonce("aaaa", 9);
+        assert_eq!(u.decrypt_str(&with_nonce).unwrap(), "aaaa");
+
+        // The default mode keeps producing the original tokens.
+        let plain = UniAz::new();
+        assert_eq!(plain.encrypt_str("aaaa"), vec![plain.encrypt('a'); 4].join(" "));
+        assert_ne!(plain.encrypt_str("aaaa"), encrypted);
+    }
+
+    #[test]
+    fn test_authenticated_str() {
+        use crate::DecryptError;
+
+        let u = UniAz::builder().key(b"secret").authenticated(true).build();
+        let encrypted = u.encrypt_str("你好世界");
+        assert_eq!(u.decrypt_str(&encrypted).unwrap(), "你好世界");
+        assert_eq!(u.decrypt_str(&u.encrypt_str("")).unwrap(), "");
+
+        // Dropping, reordering or replacing tokens is detected.
+        let tokens: Vec<&str> = encrypted.split(' ').collect();
+        let swapped = [tokens[1], tokens[0], tokens[2], tokens[3], tokens[4]].join(" ");
+        assert_eq!(u.decrypt_str(&swapped), Err(DecryptError::AuthenticationFailed));
+        let dropped = [tokens[0], tokens[2], tokens[3], tokens[4]].join(" ");
+        assert_eq!(u.decrypt_str(&dropped), Err(DecryptError::AuthenticationFailed));
+        let replaced = encrypted.replacen(tokens[0], &u.encrypt('x'), 1);
+        assert_eq!(u.decrypt_str(&replaced), Err(DecryptError::AuthenticationFailed));
+        assert_eq!(u.decrypt_str(tokens[0]), Err(DecryptError::AuthenticationFailed));
+        assert_eq!(u.decrypt_str(""), Err(DecryptError::AuthenticationFailed));
+
+        // A different key rejects the message.
+        let other = UniAz::builder().key(b"other").authenticated(true).build();
+        assert_eq!(other.decrypt_str(&encrypted), Err(DecryptError::AuthenticationFailed));
+
+        let randomized = UniAz::builder().key(b"secret").authenticated(true).randomized(true).build();
+        assert_eq!(randomized.decrypt_str(&randomized.encrypt_str("aa")).unwrap(), "aa");
+    }
+
+    #[test]
+    fn test_keyed() {
+        let u = UniAz::builder().key(b"secret").build();
+        let encrypted = u.encrypt_str("你好世界");
+        assert_ne!(encrypted, UniAz::new().encrypt_str("你好世界"));
+        assert_eq!(u.decrypt_str(&encrypted).unwrap(), "你好世界");
+        let other = UniAz::builder().key(b"other").build();
+        assert_ne!(other.decrypt_str(&encrypted).ok().as_deref(), Some("你好世界"));
+        // Headerless v1 text is unkeyed, so keyed instances read it too.
+        let v1 = UniAz::new().encrypt_str("你好世界");
+        assert_eq!(u.decrypt_str(&v1).unwrap(), "你好世界");
+    }
+
+    #[test]
+    fn test_v1_frozen() {
         let u = UniAz::new();
-        for i in 0..=0x10FFFF {
-            if let Some(c) = char::from_u32(i) {
-                let e = u.encrypt(c);
-                let p = u.decrypt(&e).unwrap();
-                assert_eq!(c, p);
+        assert_eq!(u.encrypt('你'), "abpx");
+        assert_eq!(u.encrypt_str("你好世界"), "abpx ouqz syxt ecrx");
+        assert_eq!(u.decrypt_str("abpx ouqz syxt ecrx").unwrap(), "你好世界");
+
+        // Every configuration reads v1 text, except authenticated ones.
+        let configured = UniAz::builder()
+            .randomized(true)
+            .tweak_mode(crate::TweakMode::PositionV1)
+            .envelope(true)
+            .build();
+        assert_eq!(configured.decrypt_str("abpx ouqz syxt ecrx").unwrap(), "你好世界");
+    }
+
+    #[test]
+    fn test_golden_vectors() {
+        use crate::DecryptError;
+
+        let u = UniAz::new();
+        let vectors = include_str!("../tests/vectors/golden_v1.tsv");
+        let mut count = 0;
+        for line in vectors.lines().filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let mut fields = line.split('\t');
+            let (code, token) = (fields.next().unwrap(), fields.next().unwrap());
+            let cp = u32::from_str_radix(code.strip_prefix("U+").unwrap(), 16).unwrap();
+            match char::from_u32(cp) {
+                Some(c) => {
+                    assert_eq!(u.encrypt(c), token, "encrypt({code})");
+                    assert_eq!(u.decrypt(token), Ok(c), "decrypt({token})");
+                }
+                None => {
+                    let converted = u.converter.convert(&cp.to_string()).unwrap();
+                    assert_eq!(u.cipher.encrypt(&converted, 2), token, "encode({code})");
+                    assert_eq!(u.decrypt(token), Err(DecryptError::InvalidCodepoint), "decrypt({token})");
+                }
             }
+            count += 1;
+        }
+        assert!(count > 80);
+    }
+
+    #[test]
+    fn test_envelope() {
+        use crate::DecryptError;
+
+        let u = UniAz::builder().envelope(true).key_id(3).build();
+        let encrypted = u.encrypt_str("你好");
+        let (header, body) = encrypted.split_once(' ').unwrap();
+        assert_eq!(header.len(), 11);
+        assert_eq!(body, "abpx ouqz");
+        assert_eq!(u.decrypt_str(&encrypted).unwrap(), "你好");
+        assert_eq!(u.decrypt_str(header).unwrap(), "");
+
+        assert_eq!(UniAz::new().decrypt_str(&encrypted), Err(DecryptError::UnknownKey));
+        let future = encrypted.replacen("uzc", "uzd", 1);
+        assert_eq!(u.decrypt_str(&future), Err(DecryptError::UnsupportedFormat));
+    }
+
+    #[test]
+    fn test_authenticated_downgrade() {
+        use crate::DecryptError;
+
+        let u = UniAz::builder().key(b"secret").authenticated(true).build();
+        let plain = UniAz::builder().key(b"secret").build();
+        let unauthenticated = plain.encrypt_str("你好");
+        assert_eq!(plain.decrypt_str(&unauthenticated).unwrap(), "你好");
+        assert_eq!(u.decrypt_str(&unauthenticated), Err(DecryptError::AuthenticationFailed));
+        let body = unauthenticated.split_once(' ').unwrap().1;
+        assert_eq!(u.decrypt_str(body), Err(DecryptError::AuthenticationFailed));
+        // Without the key the tag cannot be checked.
+        assert_eq!(plain.decrypt_str(&u.encrypt_str("你好")), Err(DecryptError::AuthenticationFailed));
+    }
+
+    #[test]
+    fn test_grouped_str() {
+        use crate::DecryptError;
+
+        // Grouped text decodes without the `grapheme` feature.
+        let u = UniAz::new();
+        let (e, accent, flag) = (u.encrypt('e'), u.encrypt('\u{301}'), u.encrypt('🇯'));
+        let grouped = format!("uzcaaaiaaaa {e}-{accent} {flag}");
+        assert_eq!(u.decrypt_str(&grouped).unwrap(), "e\u{301}🇯");
+        assert_eq!(
+            u.decrypt_str(&format!("uzcaaaiaaaa {e}--{accent}")),
+            Err(DecryptError::InvalidToken)
+        );
+        assert_eq!(
+            u.decrypt_str(&format!("uzcaaaaaaaa {e}-{accent}")),
+            Err(DecryptError::InvalidCipherText)
+        );
+    }
+
+    #[cfg(feature = "grapheme")]
+    #[test]
+    fn test_grapheme_clusters() {
+        let u = UniAz::builder()
+            .grapheme_clusters(true)
+            .tweak_mode(crate::TweakMode::PositionV1)
+            .build();
+        let text = "a👨‍👩‍👧🇨🇳e\u{301}";
+        let encrypted = u.encrypt_str(text);
+        let groups: Vec<&str> = encrypted.split(' ').skip(1).collect();
+        let sizes: Vec<usize> = groups.iter().map(|g| g.split('-').count()).collect();
+        assert_eq!(sizes, [1, 5, 2, 2]);
+        assert_eq!(u.decrypt_str(&encrypted).unwrap(), text);
+
+        // Truncating or reversing whole groups keeps user-perceived characters intact.
+        let plain = UniAz::builder().grapheme_clusters(true).build();
+        let encrypted = plain.encrypt_str(text);
+        let (header, groups) = encrypted.split_once(' ').unwrap();
+        let mut groups: Vec<&str> = groups.split(' ').collect();
+        let truncated = format!("{header} {}", groups[..2].join(" "));
+        assert_eq!(plain.decrypt_str(&truncated).unwrap(), "a👨‍👩‍👧");
+        groups.reverse();
+        let reversed = format!("{header} {}", groups.join(" "));
+        assert_eq!(plain.decrypt_str(&reversed).unwrap(), "e\u{301}🇨🇳👨‍👩‍👧a");
+    }
+
+    #[test]
+    fn test_preserve_lines() {
+        use crate::{DecryptError, TweakMode};
+
+        let u = UniAz::builder().preserve_lines(true).build();
+        let text = "第一行\n\n  two\tcols\r\nlast\n";
+        let encrypted = u.encrypt_str(text);
+        let lines: Vec<&str> = encrypted.split('\n').collect();
+        assert_eq!(lines.len(), text.split('\n').count() + 1);
+        assert_eq!(lines[1].split(' ').count(), 3);
+        assert_eq!(lines[2], "");
+        assert_eq!(u.decrypt_str(&encrypted).unwrap(), text);
+
+        // Editing one line of text changes only that line of output.
+        let edited = u.encrypt_str("第一行\n\n  two\tcols\r\nLAST\n");
+        let changed: Vec<usize> = (0..lines.len())
+            .filter(|&i| edited.split('\n').nth(i) != Some(lines[i]))
+            .collect();
+        assert_eq!(changed, [4]);
+
+        // Separators other than single spaces are rejected.
+        for bad in [
+            encrypted.replacen(' ', "  ", 1),
+            encrypted.replacen(' ', "\t", 1),
+            encrypted.replacen('\n', " ", 1),
+            format!("{encrypted} "),
+        ] {
+            assert!(u.decrypt_str(&bad).is_err(), "{bad:?}");
+        }
+        // The header must be on the first line.
+        assert_eq!(
+            UniAz::new().decrypt_str("\nuzcaaaqaaaa"),
+            Err(DecryptError::InvalidCipherText)
+        );
+
+        let u = UniAz::builder()
+            .preserve_lines(true)
+            .tweak_mode(TweakMode::PositionV1)
+            .key(b"secret")
+            .authenticated(true)
+            .build();
+        let encrypted = u.encrypt_str_with_nonce("ab\ncd", 7);
+        assert_eq!(encrypted.split('\n').count(), 4);
+        assert_eq!(u.decrypt_str(&encrypted).unwrap(), "ab\ncd");
+        // Moving a token across a line break invalidates the tag.
+        let lines: Vec<&str> = encrypted.split('\n').collect();
+        let (a, b) = lines[1].split_once(' ').unwrap();
+        let moved = format!("{}\n{a}\n{b} {}\n{}", lines[0], lines[2], lines[3]);
+        assert_eq!(u.decrypt_str(&moved), Err(DecryptError::AuthenticationFailed));
+    }
+
+    #[test]
+    fn test_order_preserving() {
+        use crate::{DecryptError, TokenEncoding};
+
+        let u = UniAz::builder().token_encoding(TokenEncoding::OrderPreserving).build();
+        assert_eq!(u.encrypt('\0'), "aaaaa");
+        assert_eq!(u.encrypt('你'), "abebo");
+        assert_eq!(u.encrypt(char::MAX), "clkcl");
+
+        let mut words = vec![
+            "", "a", "ab", "b", "Z", "z\u{10FFFF}", "z", "\0", "你好", "你", "😀", "\u{FFFF}",
+        ];
+        let mut keys: Vec<String> = words.iter().map(|w| u.encrypt_str(w)).collect();
+        words.sort_unstable();
+        keys.sort_unstable();
+        let decrypted: Vec<String> = keys.iter().map(|k| u.decrypt_str(k).unwrap()).collect();
+        assert_eq!(decrypted, words);
+        assert_eq!(UniAz::new().decrypt_str(&keys[5]).unwrap(), words[5]);
+
+        assert_eq!(u.decrypt("abpx"), Err(DecryptError::InvalidToken));
+        assert_eq!(u.decrypt("zzzzz"), Err(DecryptError::InvalidCodepoint));
+        // A surrogate.
+        assert_eq!(u.decrypt("adduu"), Err(DecryptError::InvalidCodepoint));
+    }
+
+    #[test]
+    fn test_auto_traits() {
+        fn assert_traits<T: Send + Sync + std::panic::UnwindSafe + std::panic::RefUnwindSafe>() {}
+        assert_traits::<UniAz>();
+        assert_traits::<crate::KeyRing>();
+    }
+
+    #[test]
+    fn test_debug_redacts_keys() {
+        let builder = UniAz::builder().key(b"hunter2").key_id(7).authenticated(true);
+        let alphabet = crate::key::keyed_alphabet(crate::ALPHABET, b"hunter2");
+        let u = builder.clone().build();
+        for debug in [format!("{builder:?}"), format!("{u:?}")] {
+            assert!(debug.contains("key_id: 7"), "{debug}");
+            assert!(!debug.contains("hunter2") && !debug.contains("104, 117"), "{debug}");
+            assert!(!debug.contains(&alphabet[..6]), "{debug}");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Order-preserving tokens cannot be combined with a key")]
+    fn test_order_preserving_with_key() {
+        UniAz::builder()
+            .token_encoding(crate::TokenEncoding::OrderPreserving)
+            .key(b"secret")
+            .build();
+    }
+
+    #[test]
+    fn test_rounds() {
+        let default = UniAz::builder().key(b"secret").build();
+        let u = UniAz::builder().key(b"secret").rounds(5).build();
+        assert_ne!(u.encrypt('你'), default.encrypt('你'));
+        assert_eq!(u.decrypt(&u.encrypt('你')), Ok('你'));
+        assert_eq!(u.decrypt_ct(&u.encrypt('你')), Ok('你'));
+        assert_eq!(u.decode_u64(&u.encode_u64(42)), Ok(42));
+        assert_eq!(u.decrypt_bytes(&u.encrypt_bytes(b"\xffab")).unwrap(), b"\xffab");
+
+        // The round count travels in a version 3 header, so instances with
+        // any count read text written with any other.
+        let text = "你好，world\n第二行";
+        let keyed = || UniAz::builder().key(b"secret");
+        let configs = [
+            (UniAz::builder(), UniAz::builder().rounds(1)),
+            (keyed().authenticated(true), keyed().rounds(5).authenticated(true).randomized(true)),
+            (keyed(), keyed().rounds(25).preserve_lines(true)),
+            (keyed(), keyed().rounds(3).compressed(true)),
+        ];
+        for (reader, writer) in configs {
+            let encrypted = writer.build().encrypt_str(text);
+            let header = encrypted.split_whitespace().next().unwrap();
+            assert!(header.starts_with("uzd") && header.len() == 12, "{header}");
+            assert_eq!(reader.build().decrypt_str(&encrypted).unwrap(), text);
+        }
+        // Default rounds keep the version 2 layout.
+        assert!(default.encrypt_str(text).starts_with("uzcaaaaaaaa "));
+        assert_eq!(UniAz::builder().rounds(2).build().encrypt_str("你"), "abpx");
+    }
+
+    #[test]
+    #[should_panic(expected = "Round count must be between 1 and 25, got 0")]
+    fn test_zero_rounds() {
+        UniAz::builder().rounds(0).build();
+    }
+
+    #[test]
+    #[cfg(feature = "ff1")]
+    fn test_ff1() {
+        use crate::{DecryptError, Ff1, TokenEncoding, ALPHABET};
+
+        let key = *b"0123456789abcdef";
+        let u = UniAz::builder().key(&key).ff1(true).build();
+        for c in ['a', '你', '😀', char::MAX] {
+            let token = u.encrypt(c);
+            assert_eq!(token.len(), 5, "{c:?}");
+            assert_eq!(u.decrypt(&token), Ok(c));
+        }
+        assert_eq!(u.decrypt("abc"), Err(DecryptError::InvalidToken));
+        assert_eq!(u.decrypt_ct(&u.encrypt('a')), Err(DecryptError::UnsupportedFormat));
+        assert_eq!(u.decode_u64(&u.encode_u64(42)), Ok(42));
+        for len in 0..=9 {
+            let bytes: Vec<u8> = (0..len).map(|i: u8| i.wrapping_mul(37)).collect();
+            assert_eq!(u.decrypt_bytes(&u.encrypt_bytes(&bytes)).unwrap(), bytes);
+        }
+
+        let text = "你好，world\n第二行";
+        let writer = UniAz::builder().key(&key).ff1(true).authenticated(true).randomized(true);
+        let reader = UniAz::builder().key(&key).authenticated(true);
+        let encrypted = writer.clone().preserve_lines(true).build().encrypt_str(text);
+        assert_eq!(writer.clone().build().decrypt_str(&encrypted).unwrap(), text);
+        // Other instances cannot read FF1 text, but FF1 instances read theirs.
+        let unsupported = Err(DecryptError::UnsupportedFormat);
+        assert_eq!(reader.clone().build().decrypt_str(&encrypted), unsupported);
+        // Crafted headers combining FF1 with compression are rejected.
+        let ff1 = UniAz::builder().key(&key).ff1(true).build();
+        assert_eq!(ff1.decrypt_str("uzcaaweaaaa abcdefghijklm"), unsupported);
+        let native = reader.build().encrypt_str(text);
+        assert_eq!(writer.clone().build().decrypt_str(&native), unsupported);
+        // The user key is the AES key, as in any FF1 implementation.
+        let standard = Ff1::new(&key, ALPHABET).unwrap();
+        let digits = u.converter.convert("20320").unwrap();
+        assert_eq!(u.encrypt('你'), standard.encrypt(&format!("{digits:a>5}"), &[0; 8]));
+
+        let spoken = writer.token_encoding(TokenEncoding::Pronounceable).build();
+        assert_eq!(spoken.decrypt_str(&spoken.encrypt_str(text)).unwrap(), text);
+    }
+
+    #[test]
+    #[cfg(feature = "ff1")]
+    fn test_ff1_id_length() {
+        let letters: Vec<String> = ('a'..='z').map(String::from).collect();
+        let builder = UniAz::builder().key(b"0123456789abcdef").ff1(true).blocklist(&letters);
+        // Every token is blocked, so all retries are spent.
+        let u = builder.min_id_length(34).build();
+        assert_eq!(u.encode_u64(42).len(), 50);
+        assert_eq!(u.decode_u64(&u.encode_u64(42)), Ok(42));
+    }
+
+    #[test]
+    #[cfg(feature = "ff1")]
+    #[should_panic(expected = "so min_id_length must be at most 34")]
+    fn test_ff1_id_too_long() {
+        UniAz::builder().key(b"0123456789abcdef").ff1(true).min_id_length(35).build();
+    }
+
+    #[test]
+    #[cfg(feature = "ff1")]
+    #[should_panic(expected = "FF1 keys must be 16, 24 or 32 bytes, got 6")]
+    fn test_ff1_key_length() {
+        UniAz::builder().key(b"secret").ff1(true).build();
+    }
+
+    #[test]
+    fn test_compressed() {
+        use crate::{DecryptError, TweakMode};
+
+        let plain = UniAz::builder().envelope(true).build();
+        let u = UniAz::builder().compressed(true).build();
+        let text = "哈哈哈哈哈哈，the cat and the hat and the bat. 哈哈哈哈哈哈！";
+        let encrypted = u.encrypt_str(text);
+        assert_eq!(encrypted.split(' ').count(), 2);
+        assert!(encrypted.len() * 3 < plain.encrypt_str(text).len());
+        assert_eq!(u.decrypt_str(&encrypted).unwrap(), text);
+        assert_eq!(u.decrypt_str(&u.encrypt_str("")).unwrap(), "");
+        // Compressed and uncompressed messages coexist.
+        assert_eq!(plain.decrypt_str(&encrypted).unwrap(), text);
+        assert_eq!(u.decrypt_str(&plain.encrypt_str(text)).unwrap(), text);
+        assert_eq!(u.decrypt_str(&UniAz::new().encrypt_str(text)).unwrap(), text);
+        // A stream is a single token.
+        let split = format!("{} {}", encrypted, encrypted.split(' ').nth(1).unwrap());
+        assert_eq!(u.decrypt_str(&split), Err(DecryptError::InvalidToken));
+        // Expansion stops at the configured limit.
+        let run = u.encrypt_str(&"x".repeat(100_000));
+        assert!(run.len() < 1000);
+        assert_eq!(u.decrypt_str(&run).unwrap().len(), 100_000);
+        let short = UniAz::builder().max_expanded_length(99_999).build();
+        assert_eq!(short.decrypt_str(&run), Err(DecryptError::TokenTooLong));
+
+        let u = UniAz::builder()
+            .compressed(true)
+            .preserve_lines(true)
+            .tweak_mode(TweakMode::PositionV1)
+            .key(b"secret")
+            .authenticated(true)
+            .build();
+        let text = "你好你好你好\n\n你好你好你好\n";
+        let encrypted = u.encrypt_str_with_nonce(text, 7);
+        let lines: Vec<&str> = encrypted.split('\n').collect();
+        assert_eq!(lines.len(), text.split('\n').count() + 2);
+        assert_eq!(u.decrypt_str(&encrypted).unwrap(), text);
+        // Blocks are tweaked by position, so repeated lines differ.
+        assert_ne!(lines[1], lines[3]);
+        let swapped = format!("{}\n{}\n\n{}\n\n{}", lines[0], lines[3], lines[1], lines[5]);
+        assert_eq!(u.decrypt_str(&swapped), Err(DecryptError::AuthenticationFailed));
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_bytes() {
+        let u = UniAz::new();
+        let data: Vec<u8> = (0..=255).rev().collect();
+        for len in 0..=data.len() {
+            let encrypted = u.encrypt_bytes(&data[..len]);
+            assert!(encrypted.chars().all(|c| c.is_ascii_lowercase()));
+            assert_eq!(u.decrypt_bytes(&encrypted).unwrap(), &data[..len]);
         }
     }
+
+    #[test]
+    fn test_decrypt_bytes_invalid() {
+        let u = UniAz::new();
+        assert!(u.decrypt_bytes("ab cd").is_err());
+        assert!(u.decrypt_bytes("abc").is_err());
+        // 26 * 26 > 256, so some two-letter tails do not decode to a single byte.
+        let overflow = (b'a'..=b'z')
+            .flat_map(|a| (b'a'..=b'z').map(move |b| String::from_utf8(vec![a, b]).unwrap()))
+            .find(|t| u.decrypt_bytes(t).is_err());
+        assert!(overflow.is_some());
+    }
+
+    #[test]
+    fn test_codepoint_samples() {
+        // Both ends of the code space and the surrogate gap; the full sweep
+        // below is left to release builds.
+        let u = UniAz::new();
+        assert_eq!(u.self_test_range(0..0x4000), Ok(0x4000));
+        assert_eq!(u.self_test_range(0xd000..0xf000), Ok(0x1800));
+        assert_eq!(u.self_test_range(0x10_c000..0x11_0000), Ok(0x4000));
+    }
+
+    #[test]
+    #[ignore = "exhaustive over all scalar values; run with `cargo test --release -- --ignored`"]
+    fn test_all_codepoints() {
+        // Every scalar value round-trips and no two share a token.
+        let u = UniAz::new();
+        assert_eq!(u.self_test(), Ok(0x110000 - 0x800));
+    }
 }
\ No newline at end of file
@@ -3,6 +3,17 @@ use uniaz::UniAz;
 
 fn main() {
     let u = UniAz::new();
+    if std::env::args().nth(1).as_deref() == Some("selftest") {
+        match u.self_test() {
+            Ok(count) => println!("自检通过: {count} 个 Unicode 标量值均可往返, 且密文互不相同"),
+            Err(err) => {
+                eprintln!("自检失败: {err}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     loop {
         print!("请输入文本: ");
         io::stdout().flush().unwrap();
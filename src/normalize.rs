@@ -0,0 +1,82 @@
+use std::borrow::Cow;
+use unicode_normalization::UnicodeNormalization;
+
+/// Unicode normalization form applied by [`UniAz::encrypt_str`](crate::UniAz::encrypt_str).
+///
+/// Canonically equivalent strings, such as `"é"` written as U+00E9 or as
+/// `"e"` followed by U+0301, encrypt to the same tokens once normalized to
+/// the same form. The compatibility forms additionally fold characters like
+/// `"ﬁ"` into `"fi"`.
+///
+/// Requires the `normalization` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Normalization {
+    /// Canonical decomposition followed by canonical composition.
+    Nfc,
+    /// Canonical decomposition.
+    Nfd,
+    /// Compatibility decomposition followed by canonical composition.
+    Nfkc,
+    /// Compatibility decomposition.
+    Nfkd,
+}
+
+impl Normalization {
+    /// Normalizes `text`, borrowing it when it is already in this form.
+    pub(crate) fn apply(self, text: &str) -> Cow<'_, str> {
+        use unicode_normalization::{is_nfc, is_nfd, is_nfkc, is_nfkd};
+
+        let normalized = match self {
+            Normalization::Nfc => is_nfc(text),
+            Normalization::Nfd => is_nfd(text),
+            Normalization::Nfkc => is_nfkc(text),
+            Normalization::Nfkd => is_nfkd(text),
+        };
+        if normalized {
+            return Cow::Borrowed(text);
+        }
+        Cow::Owned(match self {
+            Normalization::Nfc => text.nfc().collect(),
+            Normalization::Nfd => text.nfd().collect(),
+            Normalization::Nfkc => text.nfkc().collect(),
+            Normalization::Nfkd => text.nfkd().collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Normalization;
+    use crate::UniAz;
+
+    #[test]
+    fn test_apply() {
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+        assert_eq!(Normalization::Nfc.apply(decomposed), composed);
+        assert_eq!(Normalization::Nfd.apply(composed), decomposed);
+        assert_eq!(Normalization::Nfkc.apply("\u{fb01}"), "fi");
+        assert_eq!(Normalization::Nfkd.apply("\u{fb01}"), "fi");
+        assert!(matches!(Normalization::Nfc.apply(composed), std::borrow::Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_encrypt_str() {
+        let composed = "Jos\u{e9}";
+        let decomposed = "Jose\u{301}";
+        let plain = UniAz::new();
+        assert_ne!(plain.encrypt_str(composed), plain.encrypt_str(decomposed));
+
+        for form in [Normalization::Nfc, Normalization::Nfd] {
+            let u = UniAz::builder().normalization(form).build();
+            let encrypted = u.encrypt_str(composed);
+            assert_eq!(encrypted, u.encrypt_str(decomposed));
+            // Decryption returns the normalized text, not the original input.
+            assert_eq!(u.decrypt_str(&encrypted).unwrap(), form.apply(decomposed));
+        }
+
+        // Already-normalized text encrypts exactly as without normalization.
+        let nfc = UniAz::builder().normalization(Normalization::Nfc).build();
+        assert_eq!(nfc.encrypt_str(composed), plain.encrypt_str(composed));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::UniAz;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Number of code points each worker claims at a time.
+const BLOCK_SIZE: u32 = 0x4000;
+
+/// Failure found by [`UniAz::self_test`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelfTestError {
+    /// A scalar value did not decrypt back to itself.
+    RoundTrip {
+        /// The scalar value that was encrypted.
+        scalar: char,
+        /// The token it was encrypted to.
+        token: String,
+    },
+    /// Two scalar values were encrypted to the same token.
+    Collision {
+        /// The shared token.
+        token: String,
+        /// The smaller of the two scalar values.
+        first: char,
+        /// The larger of the two scalar values.
+        second: char,
+    },
+}
+
+impl std::fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SelfTestError::RoundTrip { scalar, token } => {
+                write!(f, "U+{:04X} encrypts to {token:?}, which does not decrypt back", *scalar as u32)
+            }
+            SelfTestError::Collision { token, first, second } => write!(
+                f,
+                "U+{:04X} and U+{:04X} both encrypt to {token:?}",
+                *first as u32, *second as u32
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SelfTestError {}
+
+impl UniAz {
+    /// Verifies that every Unicode scalar value survives a round trip.
+    ///
+    /// Each of the 1,112,064 scalar values is encrypted with
+    /// [`encrypt`](Self::encrypt) and decrypted with [`decrypt`](Self::decrypt),
+    /// and all tokens are checked to be pairwise distinct, so the mapping is
+    /// proven injective for this instance. The work is spread over all
+    /// available cores.
+    ///
+    /// Returns the number of scalar values checked, or the first failure found.
+    pub fn self_test(&self) -> Result<usize, SelfTestError> {
+        self.self_test_range(0..char::MAX as u32 + 1)
+    }
+
+    /// Runs [`self_test`](Self::self_test) on the scalar values in a range
+    /// of code points, so tests can afford to check part of the space.
+    pub(crate) fn self_test_range(&self, range: Range<u32>) -> Result<usize, SelfTestError> {
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let next_block = AtomicU32::new(range.start);
+
+        let results: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| scope.spawn(|| self.check_blocks(&next_block, range.end)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("self-test worker panicked"))
+                .collect()
+        });
+
+        let mut tokens = Vec::new();
+        for result in results {
+            tokens.extend(result?);
+        }
+
+        tokens.sort_unstable();
+        if let Some(pair) = tokens.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            let first = char::from_u32(pair[0].1).expect("only scalar values are recorded");
+            return Err(SelfTestError::Collision {
+                token: self.encrypt(first),
+                first,
+                second: char::from_u32(pair[1].1).expect("only scalar values are recorded"),
+            });
+        }
+        Ok(tokens.len())
+    }
+
+    /// Checks blocks of code points below `end` until none are left,
+    /// returning each scalar's packed token.
+    fn check_blocks(
+        &self,
+        next_block: &AtomicU32,
+        end: u32,
+    ) -> Result<Vec<(u64, u32)>, SelfTestError> {
+        let mut tokens = Vec::new();
+        loop {
+            let start = next_block.fetch_add(BLOCK_SIZE, Ordering::Relaxed);
+            if start >= end {
+                return Ok(tokens);
+            }
+            let end = start.saturating_add(BLOCK_SIZE).min(end);
+            for scalar in (start..end).filter_map(char::from_u32) {
+                let token = self.encrypt(scalar);
+                if self.decrypt(&token) != Ok(scalar) {
+                    return Err(SelfTestError::RoundTrip { scalar, token });
+                }
+                tokens.push((pack(&token), scalar as u32));
+            }
+        }
+    }
+}
+
+/// Packs a token of at most 13 letters into a distinct integer.
+///
+/// Letters map to 1..=26, so tokens of different lengths never collide.
+fn pack(token: &str) -> u64 {
+    debug_assert!(token.len() <= 13, "tokens are at most 13 letters long");
+    token.bytes().fold(0, |acc, b| acc * 27 + u64::from(b - b'a' + 1))
+}
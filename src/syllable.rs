@@ -0,0 +1,164 @@
+use crate::DecryptError;
+
+/// Consonants of the syllables: the proquint consonants without `p`, `t`,
+/// `n` and `s`, which are easily misheard as `b`, `d`, `m` and `f`.
+const CONSONANTS: &[u8; 12] = b"bdfghjklmrvz";
+
+/// Vowels of the syllables.
+const VOWELS: &[u8; 4] = b"aiou";
+
+/// Distinct syllables, i.e. the base of the syllable numbering.
+const SYLLABLES: u64 = (CONSONANTS.len() * VOWELS.len()) as u64;
+
+/// Longest letter token: 5 letters cover all scalar values.
+const MAX_LETTERS: usize = 5;
+
+/// Longest spoken token: 5 syllables cover all letter tokens.
+const MAX_SYLLABLES: usize = 5;
+
+/// Renders an enciphered letter token as consonant-vowel syllables for
+/// [`TokenEncoding::Pronounceable`](crate::TokenEncoding::Pronounceable).
+///
+/// Letter tokens are numbered shortest first, and the number is written in
+/// bijective base 48 with one syllable per digit, so tokens of up to 5
+/// letters take 1-5 syllables.
+pub(crate) fn render(letters: &str) -> String {
+    let rank = from_bijective(letters.bytes().map(|b| u64::from(b - b'a')), 26);
+    let mut out = String::new();
+    for digit in to_bijective(rank, SYLLABLES) {
+        out.push(CONSONANTS[(digit / 4) as usize] as char);
+        out.push(VOWELS[(digit % 4) as usize] as char);
+    }
+    out
+}
+
+/// Reads a token written by [`render`] back into enciphered letters.
+///
+/// Other spellings of a syllable are read as the syllable they sound like:
+/// upper case, `p`, `t`, `n` and `s` for `b`, `d`, `m` and `f`, `c` and `q`
+/// for `k`, `w` for `v`, `y` for `j` and `e` for `i`. They fail with
+/// [`DecryptError::NonCanonical`] unless `lenient` is set.
+pub(crate) fn parse(spoken: &str, lenient: bool) -> Result<String, DecryptError> {
+    if spoken.len() > 2 * MAX_SYLLABLES {
+        return Err(DecryptError::TokenTooLong);
+    }
+    let letters: Vec<u8> = spoken.bytes().map(heard_as).collect();
+    if letters.is_empty() || !letters.len().is_multiple_of(2) {
+        return Err(DecryptError::InvalidCipherText);
+    }
+    let mut digits = Vec::with_capacity(letters.len() / 2);
+    for pair in letters.chunks(2) {
+        let consonant = CONSONANTS.iter().position(|&c| c == pair[0]);
+        let vowel = VOWELS.iter().position(|&v| v == pair[1]);
+        match (consonant, vowel) {
+            (Some(c), Some(v)) => digits.push((c * 4 + v) as u64),
+            _ => return Err(DecryptError::InvalidCipherText),
+        }
+    }
+    if !lenient && letters != spoken.as_bytes() {
+        return Err(DecryptError::NonCanonical);
+    }
+    let letters = to_bijective(from_bijective(digits.into_iter(), SYLLABLES), 26);
+    if letters.len() > MAX_LETTERS {
+        return Err(DecryptError::InvalidCodepoint);
+    }
+    Ok(letters.into_iter().map(|d| (b'a' + d as u8) as char).collect())
+}
+
+/// Maps a letter to the syllable letter it is heard or written as.
+fn heard_as(letter: u8) -> u8 {
+    match letter.to_ascii_lowercase() {
+        b'p' => b'b',
+        b't' => b'd',
+        b'n' => b'm',
+        b's' => b'f',
+        b'c' | b'q' => b'k',
+        b'w' => b'v',
+        b'y' => b'j',
+        b'e' => b'i',
+        other => other,
+    }
+}
+
+/// Numbers digit strings shortest first: the empty string is -1, and the
+/// strings of each length follow all shorter ones in order.
+fn from_bijective(digits: impl Iterator<Item = u64>, base: u64) -> u64 {
+    digits.fold(0, |n, d| n * base + d + 1).wrapping_sub(1)
+}
+
+/// Inverse of [`from_bijective`] for non-empty strings.
+fn to_bijective(rank: u64, base: u64) -> Vec<u64> {
+    let mut digits = Vec::new();
+    let mut n = rank + 1;
+    while n > 0 {
+        n -= 1;
+        digits.push(n % base);
+        n /= base;
+    }
+    digits.reverse();
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TokenEncoding, UniAz};
+
+    #[test]
+    fn test_render_parse() {
+        assert_eq!(render("a"), "ba");
+        assert_eq!(render("z"), "ki");
+        assert_eq!(render("zzzzz"), "bigomodari");
+        for letters in ["a", "ab", "zz", "abpx", "qwert", "zzzzz"] {
+            assert_eq!(parse(&render(letters), false).unwrap(), letters);
+        }
+        // 5 syllables can number more strings than 5 letters.
+        assert_eq!(parse("zuzuzuzuzu", false), Err(DecryptError::InvalidCodepoint));
+        assert_eq!(parse("bazuzuzuzuzu", false), Err(DecryptError::TokenTooLong));
+        for bad in ["", "b", "bab", "ab", "xa", "bä"] {
+            assert_eq!(parse(bad, true), Err(DecryptError::InvalidCipherText), "{bad:?}");
+        }
+        // Misheard consonants and other spellings read as the syllable they sound like.
+        let canonical = parse("bidumofako", false);
+        assert!(canonical.is_ok());
+        for heard in ["pitunosaco", "BIDUMOFAKO", "bedumofaqo"] {
+            assert_eq!(parse(heard, false), Err(DecryptError::NonCanonical), "{heard:?}");
+            assert_eq!(parse(heard, true), canonical, "{heard:?}");
+        }
+    }
+
+    #[test]
+    fn test_misheard() {
+        use crate::DecryptError;
+
+        let u = UniAz::builder().token_encoding(TokenEncoding::Pronounceable).build();
+        let token = u.encrypt('你');
+        // The radix token of '你' is "abpx".
+        assert_eq!(token, "duhoju");
+        let lenient =
+            UniAz::builder().token_encoding(TokenEncoding::Pronounceable).lenient(true).build();
+        for heard in ["DUHOJU", "tuhoju", "duhoyu"] {
+            assert_eq!(u.decrypt(heard), Err(DecryptError::NonCanonical), "{heard:?}");
+            assert_eq!(lenient.decrypt(heard), Ok('你'), "{heard:?}");
+        }
+
+        let text = "电话 call";
+        let encrypted = u.encrypt_str(text);
+        assert_eq!(u.decrypt_str(&encrypted).unwrap(), text);
+        assert_eq!(UniAz::new().decrypt_str(&encrypted).unwrap(), text);
+    }
+
+    #[test]
+    fn test_self_test_samples() {
+        let u = UniAz::builder().token_encoding(TokenEncoding::Pronounceable).build();
+        assert_eq!(u.self_test_range(0..0x4000), Ok(0x4000));
+        assert_eq!(u.self_test_range(0x10_c000..0x11_0000), Ok(0x4000));
+    }
+
+    #[test]
+    #[ignore = "exhaustive over all scalar values; run with `cargo test --release -- --ignored`"]
+    fn test_self_test() {
+        let u = UniAz::builder().token_encoding(TokenEncoding::Pronounceable).build();
+        assert_eq!(u.self_test(), Ok(0x110000 - 0x800));
+    }
+}